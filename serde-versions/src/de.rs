@@ -0,0 +1,552 @@
+//! A deserializer adapter that reads and checks the version while a derived visitor reads the fields.
+//!
+//! The generated `Deserialize` impls pass a `VersionedDeserializer` to a `#[serde(remote)]` derive of
+//! the original type, so the fields are read straight into it in a single pass and borrowed data
+//! keeps working.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::de::value::{
+    BorrowedBytesDeserializer, BorrowedStrDeserializer, BytesDeserializer, StringDeserializer,
+    U64Deserializer,
+};
+use serde::de::{
+    DeserializeSeed, Deserializer, EnumAccess, Error, IgnoredAny, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::__private::{reads_newer, Compat, Repr};
+use crate::{Version, VersionError};
+
+/// What to check the version field against
+#[derive(Clone, Copy)]
+pub struct Check<'a> {
+    pub type_name: &'static str,
+    pub field: &'static str,
+    pub repr: Repr,
+    pub compat: Compat,
+    /// Whether newer versions are read too, with `forward`
+    pub forward: bool,
+    /// The newest numbered version read, given with `forward = N`
+    pub newest: Option<u64>,
+    pub expected: &'static Version,
+    /// Where a newer version read with `forward` is kept, to be written back with the fields it added
+    pub newer: Option<&'a Cell<Option<Version>>>,
+}
+
+impl Check<'_> {
+    pub(crate) fn verify<E: Error>(&self, found: Version) -> Result<(), E> {
+        if self.compat.accepts(self.expected, &found) {
+            Ok(())
+        } else if self.forward && reads_newer(self.expected, &found, self.newest) {
+            if let Some(newer) = self.newer {
+                newer.set(Some(found));
+            }
+            Ok(())
+        } else {
+            Err(VersionError::Mismatch {
+                type_name: self.type_name,
+                expected: self.expected.clone(),
+                found,
+            }
+            .into_de_error())
+        }
+    }
+}
+
+/// The field names of a struct with the version field in front, built once per type.
+///
+/// Formats such as bincode use the length of the field list to read a struct.
+pub struct Fields(OnceLock<&'static [&'static str]>);
+
+impl Fields {
+    pub const fn new() -> Self {
+        Fields(OnceLock::new())
+    }
+
+    pub(crate) fn with_version(
+        &'static self,
+        field: &'static str,
+        fields: &[&'static str],
+    ) -> &'static [&'static str] {
+        self.0.get_or_init(|| {
+            let mut all = vec![field];
+            all.extend_from_slice(fields);
+            Box::leak(all.into_boxed_slice())
+        })
+    }
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields::new()
+    }
+}
+
+/// Wraps a deserializer so the versioned payload reads as the unversioned type
+pub struct VersionedDeserializer<'a, D> {
+    inner: D,
+    check: Check<'a>,
+    fields: &'static Fields,
+}
+
+impl<'a, D> VersionedDeserializer<'a, D> {
+    pub fn new(inner: D, check: Check<'a>, fields: &'static Fields) -> Self {
+        VersionedDeserializer {
+            inner,
+            check,
+            fields,
+        }
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for VersionedDeserializer<'_, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.inner
+            .deserialize_any(VersionedVisitor::new(visitor, self.check))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.inner
+            .deserialize_map(VersionedVisitor::new(visitor, self.check))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let fields = self.fields.with_version(self.check.field, fields);
+        self.inner
+            .deserialize_struct(name, fields, VersionedVisitor::new(visitor, self.check))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        self.inner.deserialize_tuple_struct(
+            name,
+            len + 1,
+            VersionedVisitor::new(visitor, self.check),
+        )
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        // the version makes it a pair, derived newtype visitors also accept a sequence
+        self.inner
+            .deserialize_tuple_struct(name, 2, VersionedVisitor::new(visitor, self.check))
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let fields = self.fields.with_version(self.check.field, &[]);
+        self.inner.deserialize_struct(
+            name,
+            fields,
+            UnitVisitor {
+                visitor,
+                check: self.check,
+            },
+        )
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        // formats that aren't human readable write the version in front of the enum
+        if !self.inner.is_human_readable() {
+            return self.inner.deserialize_tuple(
+                2,
+                CompactEnumVisitor {
+                    seed: EnumSeed {
+                        name,
+                        variants,
+                        visitor,
+                    },
+                    check: self.check,
+                },
+            );
+        }
+        // the version sits next to the variant key
+        self.inner.deserialize_map(EnumVisitor {
+            visitor,
+            check: self.check,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit seq tuple identifier ignored_any
+    }
+}
+
+/// Reads the version and hands the rest of the payload to the wrapped visitor
+struct VersionedVisitor<'a, V> {
+    visitor: V,
+    check: Check<'a>,
+}
+
+impl<'a, V> VersionedVisitor<'a, V> {
+    fn new(visitor: V, check: Check<'a>) -> Self {
+        VersionedVisitor { visitor, check }
+    }
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for VersionedVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.visitor.visit_map(VersionedMap {
+            map,
+            check: self.check,
+            seen: false,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<V::Value, A::Error> {
+        match seq.next_element_seed(VersionSeed(self.check))? {
+            Some(()) => self.visitor.visit_seq(seq),
+            None => Err(VersionError::Missing.into_de_error()),
+        }
+    }
+}
+
+/// A unit struct is serialized as just its version
+struct UnitVisitor<'a, V> {
+    visitor: V,
+    check: Check<'a>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for UnitVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        let mut map = VersionedMap {
+            map,
+            check: self.check,
+            seen: false,
+        };
+        while map.next_key::<IgnoredAny>()?.is_some() {
+            map.next_value::<IgnoredAny>()?;
+        }
+        self.visitor.visit_unit()
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<V::Value, A::Error> {
+        match seq.next_element_seed(VersionSeed(self.check))? {
+            Some(()) => self.visitor.visit_unit(),
+            None => Err(VersionError::Missing.into_de_error()),
+        }
+    }
+}
+
+/// Reads an externally tagged enum from `{ version: N, Variant: ... }`
+struct EnumVisitor<'a, V> {
+    visitor: V,
+    check: Check<'a>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for EnumVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<V::Value, A::Error> {
+        let mut visitor = Some(self.visitor);
+        let mut value = None;
+        let mut seen = false;
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            if key.is(self.check.field) {
+                map.next_value_seed(VersionSeed(self.check))?;
+                seen = true;
+            } else if let Some(visitor) = visitor.take() {
+                value = Some(visitor.visit_enum(MapEnum { key, map: &mut map })?);
+            } else {
+                return Err(A::Error::custom("expected a single variant"));
+            }
+        }
+        if !seen {
+            return Err(VersionError::Missing.into_de_error());
+        }
+        value.ok_or_else(|| A::Error::custom("missing variant"))
+    }
+}
+
+/// Reads an enum from `(N, enum)`, as formats that aren't human readable write it
+struct CompactEnumVisitor<'a, V> {
+    seed: EnumSeed<V>,
+    check: Check<'a>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for CompactEnumVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.seed.visitor.expecting(formatter)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<V::Value, A::Error> {
+        match seq.next_element_seed(VersionSeed(self.check))? {
+            Some(()) => self.seed.next_of(seq),
+            None => Err(VersionError::Missing.into_de_error()),
+        }
+    }
+}
+
+/// Deserializes an enum with the wrapped visitor
+pub(crate) struct EnumSeed<V> {
+    pub name: &'static str,
+    pub variants: &'static [&'static str],
+    pub visitor: V,
+}
+
+impl<V> EnumSeed<V> {
+    /// Read the enum as the next element of `seq`, the one after the version
+    pub fn next_of<'de, A: SeqAccess<'de>>(self, mut seq: A) -> Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        seq.next_element_seed(self)?
+            .ok_or_else(|| A::Error::invalid_length(1, &"a version followed by the enum"))
+    }
+}
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for EnumSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        deserializer.deserialize_enum(self.name, self.variants, self.visitor)
+    }
+}
+
+/// Hides the version entry of a map from the wrapped visitor, checking it on the way past
+struct VersionedMap<'a, A> {
+    map: A,
+    check: Check<'a>,
+    seen: bool,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for VersionedMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        loop {
+            match self.map.next_key_seed(KeySeed)? {
+                Some(key) if key.is(self.check.field) => {
+                    self.map.next_value_seed(VersionSeed(self.check))?;
+                    self.seen = true;
+                }
+                Some(key) => return key.deserialize(seed).map(Some),
+                None if !self.seen => return Err(VersionError::Missing.into_de_error()),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        let version = if self.seen { 0 } else { 1 };
+        self.map.size_hint().map(|len| len.saturating_sub(version))
+    }
+}
+
+/// Deserializes the version and checks it straight away
+pub(crate) struct VersionSeed<'a>(pub Check<'a>);
+
+impl<'de> DeserializeSeed<'de> for VersionSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let found = self.0.repr.deserialize(deserializer)?;
+        self.0.verify(found)
+    }
+}
+
+/// A map key, kept so it can be replayed to the wrapped visitor when it isn't the version
+pub(crate) enum Key<'de> {
+    Borrowed(&'de str),
+    Owned(String),
+    BorrowedBytes(&'de [u8]),
+    OwnedBytes(Vec<u8>),
+    Index(u64),
+}
+
+impl<'de> Key<'de> {
+    pub fn is(&self, field: &str) -> bool {
+        match self {
+            Key::Borrowed(key) => *key == field,
+            Key::Owned(key) => key == field,
+            Key::BorrowedBytes(key) => *key == field.as_bytes(),
+            Key::OwnedBytes(key) => key == field.as_bytes(),
+            Key::Index(_) => false,
+        }
+    }
+
+    fn deserialize<S: DeserializeSeed<'de>, E: Error>(self, seed: S) -> Result<S::Value, E> {
+        match self {
+            Key::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+            Key::Owned(key) => seed.deserialize(StringDeserializer::new(key)),
+            Key::BorrowedBytes(key) => seed.deserialize(BorrowedBytesDeserializer::new(key)),
+            Key::OwnedBytes(key) => seed.deserialize(BytesDeserializer::new(&key)),
+            Key::Index(key) => seed.deserialize(U64Deserializer::new(key)),
+        }
+    }
+}
+
+pub(crate) struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = Key<'de>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Key<'de>, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = Key<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field name")
+    }
+
+    fn visit_borrowed_str<E: Error>(self, v: &'de str) -> Result<Key<'de>, E> {
+        Ok(Key::Borrowed(v))
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Key<'de>, E> {
+        Ok(Key::Owned(v.to_owned()))
+    }
+
+    fn visit_string<E: Error>(self, v: String) -> Result<Key<'de>, E> {
+        Ok(Key::Owned(v))
+    }
+
+    fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Key<'de>, E> {
+        Ok(Key::BorrowedBytes(v))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Key<'de>, E> {
+        Ok(Key::OwnedBytes(v.to_owned()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Key<'de>, E> {
+        Ok(Key::OwnedBytes(v))
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Key<'de>, E> {
+        Ok(Key::Index(v))
+    }
+}
+
+/// The variant of an externally tagged enum, read from a map entry
+struct MapEnum<'a, 'de, A> {
+    key: Key<'de>,
+    map: &'a mut A,
+}
+
+struct MapVariant<'a, A> {
+    map: &'a mut A,
+}
+
+impl<'a, 'de, A: MapAccess<'de>> EnumAccess<'de> for MapEnum<'a, 'de, A> {
+    type Error = A::Error;
+    type Variant = MapVariant<'a, A>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, MapVariant<'a, A>), A::Error> {
+        let variant = self.key.deserialize(seed)?;
+        Ok((variant, MapVariant { map: self.map }))
+    }
+}
+
+impl<'a, 'de, A: MapAccess<'de>> VariantAccess<'de> for MapVariant<'a, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.map.next_value::<IgnoredAny>().map(|_| ())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(TupleSeed { len, visitor })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(StructSeed { fields, visitor })
+    }
+}
+
+struct TupleSeed<V> {
+    len: usize,
+    visitor: V,
+}
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for TupleSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        deserializer.deserialize_tuple(self.len, self.visitor)
+    }
+}
+
+struct StructSeed<V> {
+    fields: &'static [&'static str],
+    visitor: V,
+}
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for StructSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        deserializer.deserialize_struct("", self.fields, self.visitor)
+    }
+}
@@ -0,0 +1,162 @@
+//! The envelope layout, `{ "version": N, "data": ... }`, chosen with `repr = "envelope"`.
+//!
+//! The value keeps its own serde layout under the content key, so sequences, primitives and
+//! `#[serde(transparent)]` types can be versioned too.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::__private::{Content, ContentDeserializer};
+use crate::de::{Check, Fields, KeySeed, VersionSeed};
+use crate::VersionError;
+
+/// Serializes a value with its own derived layout, as opposed to its versioned `Serialize` impl
+pub trait SerializeData {
+    fn serialize_data<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+}
+
+/// Deserializes a value with its own derived layout, as opposed to its versioned `Deserialize` impl
+pub trait DeserializeData<'de>: Sized {
+    fn deserialize_data<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+/// For `#[serde(with = "...")]` on the content field of the versioned struct
+pub mod data {
+    use super::{DeserializeData, SerializeData};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<T: SerializeData, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize_data(serializer)
+    }
+
+    pub fn deserialize<'de, T: DeserializeData<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        T::deserialize_data(deserializer)
+    }
+}
+
+/// Serializes `data` under `content`, next to the version
+pub struct Envelope<'a, V, T: ?Sized> {
+    pub name: &'static str,
+    pub field: &'static str,
+    pub version: V,
+    pub content: &'static str,
+    pub data: &'a T,
+}
+
+impl<V: Serialize, T: SerializeData + ?Sized> Serialize for Envelope<'_, V, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct(self.name, 2)?;
+        state.serialize_field(self.field, &self.version)?;
+        state.serialize_field(self.content, &Data(self.data))?;
+        state.end()
+    }
+}
+
+pub(crate) struct Data<'a, T: ?Sized>(pub(crate) &'a T);
+
+impl<T: SerializeData + ?Sized> Serialize for Data<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_data(serializer)
+    }
+}
+
+/// Deserialize an envelope, checking the version before reading the data.
+///
+/// Data found before the version is buffered until the version has been checked.
+pub fn deserialize_envelope<'de, T, D>(
+    deserializer: D,
+    check: Check<'_>,
+    content: &'static str,
+    fields: &'static Fields,
+) -> Result<T, D::Error>
+where
+    T: DeserializeData<'de>,
+    D: Deserializer<'de>,
+{
+    let fields = fields.with_version(check.field, &[content]);
+    deserializer.deserialize_struct(
+        check.type_name,
+        fields,
+        EnvelopeVisitor {
+            check,
+            content,
+            marker: PhantomData,
+        },
+    )
+}
+
+struct EnvelopeVisitor<'a, T> {
+    check: Check<'a>,
+    content: &'static str,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeData<'de>> Visitor<'de> for EnvelopeVisitor<'_, T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a versioned {}", self.check.type_name)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut seen = false;
+        let mut data = None;
+        let mut buffered: Option<Content> = None;
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            if key.is(self.check.field) {
+                if seen {
+                    return Err(A::Error::duplicate_field(self.check.field));
+                }
+                map.next_value_seed(VersionSeed(self.check))?;
+                seen = true;
+            } else if key.is(self.content) {
+                if data.is_some() || buffered.is_some() {
+                    return Err(A::Error::duplicate_field(self.content));
+                }
+                if seen {
+                    data = Some(map.next_value_seed(DataSeed(PhantomData))?);
+                } else {
+                    buffered = Some(map.next_value()?);
+                }
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        if !seen {
+            return Err(VersionError::Missing.into_de_error());
+        }
+        match (data, buffered) {
+            (Some(data), _) => Ok(data),
+            (None, Some(buffered)) => {
+                T::deserialize_data(ContentDeserializer::<A::Error>::new(buffered))
+            }
+            (None, None) => Err(A::Error::missing_field(self.content)),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        if seq.next_element_seed(VersionSeed(self.check))?.is_none() {
+            return Err(VersionError::Missing.into_de_error());
+        }
+        seq.next_element_seed(DataSeed(PhantomData))?
+            .ok_or_else(|| A::Error::invalid_length(1, &self))
+    }
+}
+
+pub(crate) struct DataSeed<T>(pub(crate) PhantomData<T>);
+
+impl<'de, T: DeserializeData<'de>> DeserializeSeed<'de> for DataSeed<T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize_data(deserializer)
+    }
+}
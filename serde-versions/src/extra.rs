@@ -0,0 +1,105 @@
+//! The store of fields from newer versions, added to types with `forward`.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_value::Value;
+
+use crate::__private::{KeyFormat, Repr};
+use crate::Version;
+
+/// Fields a type with `forward` doesn't know, read from newer payloads and written back out when
+/// serializing, so values pass through older code without losing data. They are written back under
+/// the newer version they were read from, so newer code reads them again.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Extra {
+    fields: BTreeMap<String, Value>,
+    version: Option<Version>,
+}
+
+impl Extra {
+    /// Whether the payload had no unknown fields
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The number of unknown fields
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether an unknown field with the given name was read
+    pub fn contains(&self, name: &str) -> bool {
+        self.fields.contains_key(name)
+    }
+
+    /// The names of the unknown fields
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.fields.keys().map(String::as_str)
+    }
+
+    /// The newer version the payload was written as, if it was newer than the type
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+}
+
+impl Serialize for Extra {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.fields.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Extra {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        BTreeMap::deserialize(deserializer).map(|fields| Extra {
+            fields,
+            version: None,
+        })
+    }
+}
+
+/// Keep the newer version recorded while reading, once the value holding `extra` has been read
+pub fn keep_newer(extra: &mut Extra, newer: &Cell<Option<Version>>) {
+    if let Some(version) = newer.take() {
+        extra.version = Some(version);
+    }
+}
+
+/// The version written by a type with `forward`: its own, or the newer one its unknown fields were
+/// read from
+pub enum WrittenVersion<'a, V> {
+    Own(V),
+    Read(Repr, &'a Version),
+}
+
+impl<'a, V> WrittenVersion<'a, V> {
+    pub fn new(own: V, repr: Repr, extra: &'a Extra) -> Self {
+        match &extra.version {
+            Some(version) if !extra.is_empty() => WrittenVersion::Read(repr, version),
+            _ => WrittenVersion::Own(own),
+        }
+    }
+}
+
+impl<V: Display> WrittenVersion<'_, V> {
+    /// The key naming the written version, for the keyed layout
+    pub fn key(&self, key: KeyFormat) -> String {
+        match self {
+            WrittenVersion::Own(version) => key.format(version),
+            WrittenVersion::Read(_, version) => key.format(version),
+        }
+    }
+}
+
+impl<V: Serialize> Serialize for WrittenVersion<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            WrittenVersion::Own(version) => version.serialize(serializer),
+            WrittenVersion::Read(repr, version) => repr.serialize(version, serializer),
+        }
+    }
+}
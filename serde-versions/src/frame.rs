@@ -0,0 +1,257 @@
+//! Raw version framing, chosen with `frame = "varint"` or `frame = "fixed"`.
+//!
+//! The version is written as a header in front of the value, which is encoded with its own layout
+//! by a [`Codec`], bincode unless another one is given. The header can be read without knowing
+//! anything about the value.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use bincode::Options;
+use serde::de::{Deserialize, DeserializeOwned, DeserializeSeed, Deserializer};
+use serde::ser::Serialize;
+
+use crate::__private::{semver_or_text, Repr};
+use crate::envelope::{Data, DataSeed, DeserializeData, SerializeData};
+use crate::{Version, VersionError};
+
+/// How the version header is written, given by `frame`
+#[derive(Clone, Copy)]
+pub enum Header {
+    /// LEB128 numbers, strings prefixed with their LEB128 length
+    Varint,
+    /// Little endian numbers as wide as `repr`, strings prefixed with their length as a `u64`
+    Fixed,
+}
+
+impl Header {
+    /// Write the header for `version`, a version of the given `repr`
+    pub fn write<W: Write>(self, mut writer: W, repr: Repr, version: &Version) -> io::Result<()> {
+        match version {
+            Version::Number(number) => self.write_number(&mut writer, repr, *number),
+            Version::Text(text) => self.write_text(&mut writer, text),
+            Version::Semver {
+                major,
+                minor,
+                patch,
+            } => self.write_text(&mut writer, &format!("{}.{}.{}", major, minor, patch)),
+        }
+    }
+
+    /// Read a header written by [`Header::write`] with the same `repr`
+    pub fn read<R: Read>(self, mut reader: R, repr: Repr) -> Result<Version, FrameError> {
+        // a varint wider than `repr` is reported as a mismatch, like any other version
+        let text = match repr {
+            Repr::Str | Repr::Semver => self.read_text(&mut reader)?,
+            _ => return Ok(Version::Number(self.read_number(&mut reader, width(repr))?)),
+        };
+        Ok(match repr {
+            Repr::Semver => semver_or_text(text),
+            _ => Version::from(text),
+        })
+    }
+
+    fn write_number<W: Write>(self, writer: &mut W, repr: Repr, mut number: u64) -> io::Result<()> {
+        match self {
+            Header::Varint => loop {
+                let byte = (number & 0x7f) as u8;
+                number >>= 7;
+                if number == 0 {
+                    return writer.write_all(&[byte]);
+                }
+                writer.write_all(&[byte | 0x80])?;
+            },
+            Header::Fixed => writer.write_all(&number.to_le_bytes()[..width(repr)]),
+        }
+    }
+
+    fn write_text<W: Write>(self, writer: &mut W, text: &str) -> io::Result<()> {
+        self.write_number(writer, Repr::U64, text.len() as u64)?;
+        writer.write_all(text.as_bytes())
+    }
+
+    fn read_text<R: Read>(self, reader: &mut R) -> io::Result<String> {
+        let len = self.read_number(reader, 8)?;
+        let mut text = Vec::new();
+        reader.by_ref().take(len).read_to_end(&mut text)?;
+        if (text.len() as u64) < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn read_number<R: Read>(self, reader: &mut R, width: usize) -> io::Result<u64> {
+        match self {
+            Header::Varint => {
+                let mut number = 0u64;
+                for shift in (0..64).step_by(7) {
+                    let mut byte = [0];
+                    reader.read_exact(&mut byte)?;
+                    let bits = u64::from(byte[0] & 0x7f);
+                    if shift == 63 && bits > 1 {
+                        break;
+                    }
+                    number |= bits << shift;
+                    if byte[0] & 0x80 == 0 {
+                        return Ok(number);
+                    }
+                }
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "version header overflows a u64",
+                ))
+            }
+            Header::Fixed => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes[..width])?;
+                Ok(u64::from_le_bytes(bytes))
+            }
+        }
+    }
+}
+
+/// Bytes taken by a fixed width number of the given `repr`
+fn width(repr: Repr) -> usize {
+    match repr {
+        Repr::U8 => 1,
+        Repr::U16 => 2,
+        Repr::U32 => 4,
+        Repr::U64 | Repr::Str | Repr::Semver => 8,
+    }
+}
+
+/// Encodes the value written after the version header, e.g. with postcard instead of bincode:
+///
+/// ```ignore
+/// struct Postcard;
+///
+/// impl Codec for Postcard {
+///     type Error = postcard::Error;
+///
+///     fn encode<W: Write, T: Serialize + ?Sized>(&self, writer: W, value: &T) -> Result<(), Self::Error> {
+///         postcard::to_io(value, writer).map(|_| ())
+///     }
+///
+///     fn decode<R: Read, T: DeserializeOwned>(&self, reader: R) -> Result<T, Self::Error> {
+///         postcard::from_io((reader, &mut [0; 1024])).map(|(value, _)| value)
+///     }
+/// }
+///
+/// s.encode_versioned_with(&Postcard, &mut bytes)?;
+/// let s = S::decode_versioned_with(&Postcard, &bytes[..])?;
+/// ```
+pub trait Codec {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Encode `value` into `writer`
+    fn encode<W: Write, T: Serialize + ?Sized>(
+        &self,
+        writer: W,
+        value: &T,
+    ) -> Result<(), Self::Error>;
+
+    /// Decode a value encoded by [`Codec::encode`] from `reader`
+    fn decode<R: Read, T: DeserializeOwned>(&self, reader: R) -> Result<T, Self::Error>;
+}
+
+/// bincode with its default configuration, as used by `bincode::serialize`. The codec of
+/// `encode_versioned` and `decode_versioned`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Bincode {
+    fn options() -> impl Options {
+        bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+    }
+}
+
+impl Codec for Bincode {
+    type Error = bincode::Error;
+
+    fn encode<W: Write, T: Serialize + ?Sized>(&self, writer: W, value: &T) -> bincode::Result<()> {
+        Bincode::options().serialize_into(writer, value)
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(&self, reader: R) -> bincode::Result<T> {
+        Bincode::options().deserialize_from(reader)
+    }
+}
+
+/// Encode a value with its own layout
+pub fn encode_data<C: Codec, W: Write, T: SerializeData + ?Sized>(
+    codec: &C,
+    writer: W,
+    value: &T,
+) -> Result<(), FrameError> {
+    codec
+        .encode(writer, &Data(value))
+        .map_err(|err| FrameError::Body(Box::new(err)))
+}
+
+/// Decode a value encoded by [`encode_data`]
+pub fn decode_data<C: Codec, R: Read, T: for<'de> DeserializeData<'de>>(
+    codec: &C,
+    reader: R,
+) -> Result<T, FrameError> {
+    codec
+        .decode(reader)
+        .map(|OwnData(value)| value)
+        .map_err(|err| FrameError::Body(Box::new(err)))
+}
+
+/// A value deserialized with its own layout, so codecs only need `Deserialize`
+struct OwnData<T>(T);
+
+impl<'de, T: DeserializeData<'de>> Deserialize<'de> for OwnData<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DataSeed(std::marker::PhantomData)
+            .deserialize(deserializer)
+            .map(OwnData)
+    }
+}
+
+/// Error produced by `encode_versioned` and `decode_versioned`
+#[derive(Debug)]
+pub enum FrameError {
+    /// Reading or writing the version header failed
+    Io(io::Error),
+    /// The header carries a version the type can't read
+    Version(VersionError),
+    /// Encoding or decoding the value failed
+    Body(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Io(err) => write!(f, "invalid version header: {}", err),
+            FrameError::Version(err) => write!(f, "{}", err),
+            FrameError::Body(err) => write!(f, "invalid body: {}", err),
+        }
+    }
+}
+
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FrameError::Io(err) => Some(err),
+            FrameError::Version(err) => Some(err),
+            FrameError::Body(err) => Some(&**err),
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(err: io::Error) -> Self {
+        FrameError::Io(err)
+    }
+}
+
+impl From<VersionError> for FrameError {
+    fn from(err: VersionError) -> Self {
+        FrameError::Version(err)
+    }
+}
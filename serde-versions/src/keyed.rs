@@ -0,0 +1,190 @@
+//! The keyed layout, `{ "v3": ... }`, chosen with `repr = "keyed"`.
+//!
+//! The version is the only key of a map, so it is known before the value is read. The value keeps
+//! its own serde layout, as in the envelope layout.
+
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de::{Deserializer, Error, IgnoredAny, MapAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_value::Value;
+
+use crate::__private::{Content, Repr};
+use crate::de::Check;
+use crate::envelope::{Data, DataSeed, DeserializeData, SerializeData};
+use crate::{Version, VersionError};
+
+/// How the version is written into the key, `{}` standing for the version, e.g. `v{}`
+#[derive(Clone, Copy)]
+pub struct KeyFormat {
+    pub prefix: &'static str,
+    pub suffix: &'static str,
+}
+
+impl KeyFormat {
+    /// The key naming the given version
+    pub fn format(&self, version: impl Display) -> String {
+        format!("{}{}{}", self.prefix, version, self.suffix)
+    }
+
+    /// The version part of a key, if it has this format
+    fn version<'k>(&self, key: &'k str) -> Option<&'k str> {
+        key.strip_prefix(self.prefix)?.strip_suffix(self.suffix)
+    }
+
+    /// The version in a key, if it is a version key
+    pub(crate) fn parse(&self, key: &str, repr: Repr) -> Option<Version> {
+        self.version(key).and_then(|version| repr.parse(version))
+    }
+
+    /// Read the version out of a key
+    pub(crate) fn read<E: Error>(&self, key: &str, repr: Repr) -> Result<Version, E> {
+        self.parse(key, repr)
+            .ok_or_else(|| E::invalid_value(Unexpected::Str(key), &"a version key"))
+    }
+}
+
+/// Serializes `data` as the only value of a map, under `key`
+pub struct Keyed<'a, T: ?Sized> {
+    pub key: &'a str,
+    pub data: &'a T,
+}
+
+impl<T: SerializeData + ?Sized> Serialize for Keyed<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.key, &Data(self.data))?;
+        map.end()
+    }
+}
+
+/// Deserialize a keyed value, checking the version in the key before reading the value
+pub fn deserialize_keyed<'de, T, D>(
+    deserializer: D,
+    check: Check<'_>,
+    key: KeyFormat,
+) -> Result<T, D::Error>
+where
+    T: DeserializeData<'de>,
+    D: Deserializer<'de>,
+{
+    deserializer
+        .deserialize_map(KeyedVisitor {
+            read: CheckKey { check, key },
+            marker: PhantomData,
+        })
+        .map(|(_, data)| data)
+}
+
+/// Deserialize a keyed value along with its version, whatever it is
+pub fn deserialize_keyed_versioned<'de, V, T, D>(
+    deserializer: D,
+    type_name: &'static str,
+    key: KeyFormat,
+) -> Result<(V, T), D::Error>
+where
+    V: FromStr,
+    T: DeserializeData<'de>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_map(KeyedVisitor {
+        read: ParseKey {
+            type_name,
+            key,
+            marker: PhantomData,
+        },
+        marker: PhantomData,
+    })
+}
+
+/// Read the version out of a buffered keyed payload
+pub fn keyed_version<E: Error>(
+    content: &Content,
+    key: KeyFormat,
+    repr: Repr,
+) -> Result<Version, E> {
+    match content {
+        Value::Map(map) if map.len() == 1 => match map.keys().next() {
+            Some(Value::String(found)) => key.read(found, repr),
+            _ => Err(VersionError::Missing.into_de_error()),
+        },
+        _ => Err(VersionError::Missing.into_de_error()),
+    }
+}
+
+/// Turns the key of a keyed payload into a version
+trait ReadKey {
+    type Version;
+
+    fn type_name(&self) -> &'static str;
+
+    fn read<E: Error>(&self, key: &str) -> Result<Self::Version, E>;
+}
+
+/// Checks the version in the key against the expected one
+struct CheckKey<'a> {
+    check: Check<'a>,
+    key: KeyFormat,
+}
+
+impl ReadKey for CheckKey<'_> {
+    type Version = ();
+
+    fn type_name(&self) -> &'static str {
+        self.check.type_name
+    }
+
+    fn read<E: Error>(&self, key: &str) -> Result<(), E> {
+        self.check.verify(self.key.read(key, self.check.repr)?)
+    }
+}
+
+/// Parses the version in the key as the version field of the versioned struct
+struct ParseKey<V> {
+    type_name: &'static str,
+    key: KeyFormat,
+    marker: PhantomData<V>,
+}
+
+impl<V: FromStr> ReadKey for ParseKey<V> {
+    type Version = V;
+
+    fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    fn read<E: Error>(&self, key: &str) -> Result<V, E> {
+        self.key
+            .version(key)
+            .and_then(|version| version.parse().ok())
+            .ok_or_else(|| E::invalid_value(Unexpected::Str(key), &"a version key"))
+    }
+}
+
+struct KeyedVisitor<R, T> {
+    read: R,
+    marker: PhantomData<T>,
+}
+
+impl<'de, R: ReadKey, T: DeserializeData<'de>> Visitor<'de> for KeyedVisitor<R, T> {
+    type Value = (R::Version, T);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a versioned {}", self.read.type_name())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let key: String = match map.next_key()? {
+            Some(key) => key,
+            None => return Err(VersionError::Missing.into_de_error()),
+        };
+        let version = self.read.read(&key)?;
+        let data = map.next_value_seed(DataSeed(PhantomData))?;
+        if map.next_key::<IgnoredAny>()?.is_some() {
+            return Err(A::Error::invalid_length(2, &"a single version key"));
+        }
+        Ok((version, data))
+    }
+}
@@ -0,0 +1,265 @@
+//! # Serde Versions
+//!
+//!  Runtime support for [`serde_versions_derive`](https://docs.rs/serde-versions-derive).
+//!
+//!  A proc-macro crate can only export macros, so the types used by the code that
+//!  `#[version]` generates live here. The `version` attribute is re-exported so a
+//!  single dependency is enough.
+//!
+//! usage:
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! use serde_versions::{version, VersionError};
+//! use std::convert::TryFrom;
+//!
+//! #[version(3)]
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//!
+//! let v = S { i: 0 }.into_versioned();
+//! match S::try_from(v) {
+//!     Ok(_) => {}
+//!     Err(VersionError::Mismatch { expected, found, .. }) => println!("{} != {}", expected, found),
+//!     Err(e) => println!("{}", e),
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+
+pub use serde_versions_derive::version;
+
+mod de;
+mod envelope;
+mod extra;
+#[cfg(feature = "bincode")]
+mod frame;
+mod keyed;
+mod peek;
+mod stream;
+
+pub use extra::Extra;
+#[cfg(feature = "bincode")]
+pub use frame::{Bincode, Codec, FrameError};
+pub use peek::peek_version;
+
+#[doc(hidden)]
+#[path = "private.rs"]
+pub mod __private;
+
+/// A version as written in a payload: a number, a string such as `"2024-01"`, or a semantic
+/// version such as `"1.4.0"`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Version {
+    Number(u64),
+    Text(Cow<'static, str>),
+    Semver { major: u64, minor: u64, patch: u64 },
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::Number(number) => write!(f, "{}", number),
+            Version::Text(text) => write!(f, "{:?}", text),
+            Version::Semver {
+                major,
+                minor,
+                patch,
+            } => write!(f, "{}.{}.{}", major, minor, patch),
+        }
+    }
+}
+
+macro_rules! version_from_number {
+    ($($ty:ty)*) => {
+        $(
+            impl From<$ty> for Version {
+                fn from(number: $ty) -> Self {
+                    Version::Number(number.into())
+                }
+            }
+        )*
+    };
+}
+
+version_from_number!(u8 u16 u32 u64);
+
+impl From<&'static str> for Version {
+    fn from(text: &'static str) -> Self {
+        Version::Text(Cow::Borrowed(text))
+    }
+}
+
+impl From<String> for Version {
+    fn from(text: String) -> Self {
+        Version::Text(Cow::Owned(text))
+    }
+}
+
+/// Error produced when a versioned payload can't be turned back into the unversioned type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionError {
+    /// The payload carries a different version than the type expects.
+    Mismatch {
+        type_name: &'static str,
+        expected: Version,
+        found: Version,
+    },
+    /// The payload carries no version at all.
+    Missing,
+}
+
+impl VersionError {
+    /// Convert into the error type of any serde deserializer.
+    pub fn into_de_error<E: serde::de::Error>(self) -> E {
+        E::custom(self)
+    }
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionError::Mismatch {
+                type_name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected version {}, found {}",
+                type_name, expected, found
+            ),
+            VersionError::Missing => write!(f, "missing version"),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+/// Implemented by `#[version]` for every versioned type, so code can be generic over them.
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// use serde_versions::{version, Versioned};
+///
+/// #[version(3)]
+/// #[derive(Serialize, Deserialize)]
+/// struct S {
+///     i: i32,
+/// }
+///
+/// fn describe<T: Versioned>() -> String {
+///     format!("{} v{}", std::any::type_name::<T>(), T::VERSION)
+/// }
+///
+/// assert!(describe::<S>().ends_with("S v3"));
+/// ```
+pub trait Versioned: Sized {
+    /// The version this type writes
+    const VERSION: Version;
+
+    /// The generated struct carrying the version next to the value, e.g. `_Sv3` for `S`
+    type Versioned;
+
+    /// Convert into the versioned struct
+    fn into_versioned(self) -> Self::Versioned;
+
+    /// Convert back from the versioned struct, checking its version
+    fn try_from_versioned(versioned: Self::Versioned) -> Result<Self, VersionError>;
+}
+
+/// A version given to [`Downgrade::serialize_as_version`]: a number, a string, which is read as a
+/// semantic version when it is one, or a [`Version`].
+pub trait IntoVersion {
+    fn into_version(self) -> Version;
+}
+
+impl IntoVersion for Version {
+    fn into_version(self) -> Version {
+        self
+    }
+}
+
+impl IntoVersion for u64 {
+    fn into_version(self) -> Version {
+        Version::Number(self)
+    }
+}
+
+impl IntoVersion for &str {
+    fn into_version(self) -> Version {
+        __private::semver_or_text(self.to_owned())
+    }
+}
+
+impl IntoVersion for String {
+    fn into_version(self) -> Version {
+        __private::semver_or_text(self)
+    }
+}
+
+/// Writing a value as one of its older versions, so services not yet upgraded can read it.
+///
+/// Implemented by `#[version]` for types without `previous`, which only write their own version,
+/// and for types with `downgrade`, which convert to `previous` with `TryFrom<Self>` step by step.
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use std::convert::TryFrom;
+/// use serde_versions::{version, Downgrade};
+///
+/// #[version(1)]
+/// #[derive(Serialize, Deserialize)]
+/// struct SV1 {
+///     i: i32,
+/// }
+///
+/// #[version(2, previous = SV1, downgrade)]
+/// #[derive(Serialize, Deserialize)]
+/// struct S {
+///     i: i32,
+///     j: Option<i32>,
+/// }
+///
+/// # impl From<SV1> for S {
+/// #     fn from(s: SV1) -> S {
+/// #         S { i: s.i, j: None }
+/// #     }
+/// # }
+/// impl TryFrom<S> for SV1 {
+///     type Error = &'static str;
+///
+///     fn try_from(s: S) -> Result<SV1, Self::Error> {
+///         match s.j {
+///             None => Ok(SV1 { i: s.i }),
+///             Some(_) => Err("`j` can't be written as version 1"),
+///         }
+///     }
+/// }
+///
+/// let mut json = Vec::new();
+/// let s = S { i: 1, j: None };
+/// s.serialize_as_version(1, &mut serde_json::Serializer::new(&mut json))
+///     .unwrap();
+/// assert_eq!(json, br#"{"version":1,"i":1}"#);
+/// ```
+pub trait Downgrade: serde::Serialize + Sized {
+    /// Serialize as the given version, converting down through `previous` as needed. Fails if a
+    /// conversion does, e.g. because it would lose information, or if no type writes the version
+    fn serialize_as_version<S: serde::Serializer>(
+        self,
+        version: impl IntoVersion,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+
+    /// Whether this type or one in its `previous` chain writes `version`, checked before any
+    /// conversion runs
+    fn writes(version: &Version) -> bool;
+
+    /// Convert into the older version `T`, with `TryFrom<Self> for T`
+    fn to_version<T: TryFrom<Self>>(self) -> Result<T, T::Error> {
+        T::try_from(self)
+    }
+}
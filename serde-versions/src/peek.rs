@@ -0,0 +1,142 @@
+//! Reading only the version of a payload, skipping everything else with `IgnoredAny`.
+
+use std::fmt;
+
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::__private::{KeyFormat, Repr};
+use crate::de::KeySeed;
+use crate::{Version, VersionError};
+
+/// Implemented for `#[version]` types, reading the version the way the type writes it.
+pub trait Peek {
+    /// Read the version of a payload written by this type, without reading anything else
+    fn peek<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Version, D::Error>;
+}
+
+/// Read the version of a payload written by `S`, skipping the rest of it.
+///
+/// This lets a payload be routed by its version before choosing the type to deserialize it as.
+/// Only the layout of `S` is known, so older versions have to put their version the same way.
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use serde_versions::{peek_version, version, Version};
+/// #[version(3)]
+/// #[derive(Serialize, Deserialize)]
+/// struct S {
+///     i: i32,
+/// }
+///
+/// let mut deserializer = serde_json::Deserializer::from_str(r#"{"i":0,"version":2}"#);
+/// let version = peek_version::<S, _>(&mut deserializer).unwrap();
+/// assert_eq!(version, Version::Number(2));
+/// ```
+pub fn peek_version<'de, S: Peek, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Version, D::Error> {
+    S::peek(deserializer)
+}
+
+/// Read the version field of a struct, or the first element of a sequence
+pub fn peek_field<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    type_name: &'static str,
+    fields: &'static [&'static str],
+    repr: Repr,
+) -> Result<Version, D::Error> {
+    deserializer.deserialize_struct(
+        type_name,
+        fields,
+        FieldVisitor {
+            type_name,
+            field: fields[0],
+            repr,
+        },
+    )
+}
+
+/// Read the version out of the key of a keyed payload
+pub fn peek_keyed<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    type_name: &'static str,
+    key: KeyFormat,
+    repr: Repr,
+) -> Result<Version, D::Error> {
+    deserializer.deserialize_map(KeyVisitor {
+        type_name,
+        key,
+        repr,
+    })
+}
+
+struct FieldVisitor {
+    type_name: &'static str,
+    field: &'static str,
+    repr: Repr,
+}
+
+impl<'de> Visitor<'de> for FieldVisitor {
+    type Value = Version;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a versioned {}", self.type_name)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Version, A::Error> {
+        let mut version = None;
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            if version.is_none() && key.is(self.field) {
+                version = Some(map.next_value_seed(ReprSeed(self.repr))?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        version.ok_or_else(|| VersionError::Missing.into_de_error())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Version, A::Error> {
+        let version = seq
+            .next_element_seed(ReprSeed(self.repr))?
+            .ok_or_else(|| VersionError::Missing.into_de_error())?;
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(version)
+    }
+}
+
+struct KeyVisitor {
+    type_name: &'static str,
+    key: KeyFormat,
+    repr: Repr,
+}
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Version;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a versioned {}", self.type_name)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Version, A::Error> {
+        let key: String = match map.next_key()? {
+            Some(key) => key,
+            None => return Err(VersionError::Missing.into_de_error()),
+        };
+        let version = self.key.read(&key, self.repr)?;
+        map.next_value::<IgnoredAny>()?;
+        while map.next_key::<IgnoredAny>()?.is_some() {
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(version)
+    }
+}
+
+struct ReprSeed(Repr);
+
+impl<'de> DeserializeSeed<'de> for ReprSeed {
+    type Value = Version;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Version, D::Error> {
+        self.0.deserialize(deserializer)
+    }
+}
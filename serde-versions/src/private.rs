@@ -0,0 +1,542 @@
+//! Support code for the impls generated by `#[version]`. Not public API.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, SeqAccess};
+use serde::ser::{Serialize, Serializer};
+use serde_value::{Value, ValueDeserializer};
+
+use crate::{Version, VersionError};
+
+pub use crate::de::{Check, Fields, VersionedDeserializer};
+pub use crate::envelope::{data, deserialize_envelope, DeserializeData, Envelope, SerializeData};
+pub use crate::extra::{keep_newer, WrittenVersion};
+#[cfg(feature = "bincode")]
+pub use crate::frame::{decode_data, encode_data, Header};
+pub use crate::keyed::{
+    deserialize_keyed, deserialize_keyed_versioned, keyed_version, KeyFormat, Keyed,
+};
+pub use crate::peek::{peek_field, peek_keyed, Peek};
+pub use crate::stream::{
+    deserialize_stream, entry_data, max_len, next_data, ReadRest, SeqRest, Stream,
+};
+pub use serde;
+
+/// A payload buffered so it can be inspected before choosing the type to deserialize it as.
+pub type Content = Value;
+
+/// Deserializer reading from a buffered [`Content`]
+pub type ContentDeserializer<E> = ValueDeserializer<E>;
+
+/// The type a version is written as, given by `repr`
+#[derive(Clone, Copy)]
+pub enum Repr {
+    U8,
+    U16,
+    U32,
+    U64,
+    Str,
+    Semver,
+}
+
+impl Repr {
+    /// Deserialize a version written as this type
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Version, D::Error> {
+        match self {
+            Repr::U8 => u8::deserialize(deserializer).map(Version::from),
+            Repr::U16 => u16::deserialize(deserializer).map(Version::from),
+            Repr::U32 => u32::deserialize(deserializer).map(Version::from),
+            Repr::U64 => u64::deserialize(deserializer).map(Version::from),
+            Repr::Str => String::deserialize(deserializer).map(Version::from),
+            Repr::Semver => String::deserialize(deserializer).map(semver_or_text),
+        }
+    }
+
+    /// Serialize a version read as this type back the same way
+    pub fn serialize<S: Serializer>(
+        self,
+        version: &Version,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match (self, version) {
+            (Repr::U8, Version::Number(number)) => serializer.serialize_u8(*number as u8),
+            (Repr::U16, Version::Number(number)) => serializer.serialize_u16(*number as u16),
+            (Repr::U32, Version::Number(number)) => serializer.serialize_u32(*number as u32),
+            (Repr::U64, Version::Number(number)) => serializer.serialize_u64(*number),
+            (_, Version::Text(text)) => serializer.serialize_str(text),
+            (_, version) => serializer.collect_str(version),
+        }
+    }
+
+    /// Parse a version written as text, e.g. in a key
+    pub fn parse(self, text: &str) -> Option<Version> {
+        match self {
+            Repr::U8 => text.parse::<u8>().ok().map(Version::from),
+            Repr::U16 => text.parse::<u16>().ok().map(Version::from),
+            Repr::U32 => text.parse::<u32>().ok().map(Version::from),
+            Repr::U64 => text.parse::<u64>().ok().map(Version::from),
+            Repr::Str => Some(Version::from(text.to_owned())),
+            Repr::Semver => Some(semver_or_text(text.to_owned())),
+        }
+    }
+}
+
+/// Parse `MAJOR.MINOR.PATCH`, keeping anything else as text so it is reported as a mismatch
+pub fn semver_or_text(text: String) -> Version {
+    let mut parts = text.split('.').map(|part| {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            None
+        } else {
+            part.parse::<u64>().ok()
+        }
+    });
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Version::Semver {
+            major,
+            minor,
+            patch,
+        },
+        _ => Version::from(text),
+    }
+}
+
+/// Which payload versions a type reads, given by `compat`
+#[derive(Clone, Copy)]
+pub enum Compat {
+    /// Only its own version
+    Exact,
+    /// Semantic versions with the same major version
+    Major,
+    /// Semantic versions with the same major version and a minor version no higher than its own
+    Minor,
+    /// Numbers no higher than its own, for types describing their history with `#[versioned]`
+    Older,
+}
+
+impl Compat {
+    /// Whether a type written as `expected` reads a payload written as `found`
+    pub fn accepts(self, expected: &Version, found: &Version) -> bool {
+        match (self, expected, found) {
+            (Compat::Exact, _, _) => expected == found,
+            (
+                Compat::Major,
+                Version::Semver { major, .. },
+                Version::Semver {
+                    major: found_major, ..
+                },
+            ) => major == found_major,
+            (
+                Compat::Minor,
+                Version::Semver { major, minor, .. },
+                Version::Semver {
+                    major: found_major,
+                    minor: found_minor,
+                    ..
+                },
+            ) => major == found_major && found_minor <= minor,
+            (Compat::Older, Version::Number(expected), Version::Number(found)) => found <= expected,
+            _ => false,
+        }
+    }
+}
+
+/// Whether a type with `forward` reads a payload written by a newer version: a higher number up
+/// to `newest`, or a semantic version with the same major version
+pub fn reads_newer(expected: &Version, found: &Version, newest: Option<u64>) -> bool {
+    match (expected, found) {
+        (Version::Number(expected), Version::Number(found)) => {
+            found > expected && newest.is_some_and(|newest| *found <= newest)
+        }
+        (
+            Version::Semver { major, .. },
+            Version::Semver {
+                major: found_major, ..
+            },
+        ) => major == found_major,
+        _ => false,
+    }
+}
+
+/// Serializes `readable` in human readable formats and `compact` in the others, e.g. an enum with
+/// its version next to the variant key, or its version followed by it
+pub struct ByFormat<R, C> {
+    pub readable: R,
+    pub compact: C,
+}
+
+impl<R: Serialize, C: Serialize> Serialize for ByFormat<R, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            self.readable.serialize(serializer)
+        } else {
+            self.compact.serialize(serializer)
+        }
+    }
+}
+
+/// Implemented for every `#[version]` type so it can be the `previous` of another one.
+pub trait Migrate: Sized {
+    /// The version written by this type. A reference so it can be compared in constants
+    const VERSION: &'static Version;
+
+    /// Whether this type itself reads the given version, following its `compat` rule
+    fn reads(version: &Version) -> bool;
+
+    /// Whether this type, or one of its predecessors, can read the given version
+    fn accepts(version: &Version) -> bool;
+
+    /// Read the version out of a buffered payload, looking at the version fields of this type
+    /// and of its predecessors
+    fn version_of<E: Error>(content: &Content) -> Result<Version, E>;
+
+    /// Give a buffered payload written without a version the version assumed by `missing`, on
+    /// this type or one of its predecessors. Gives the payload back if none assumes one
+    fn assume_version(content: Content) -> Result<(Version, Content), Content>;
+
+    /// Deserialize a buffered payload whose version is `version`, upgrading as needed
+    fn from_content<E: Error>(version: Version, content: Content) -> Result<Self, E>;
+
+    /// How payloads of this type and its predecessors start
+    const STREAM: Stream;
+
+    /// Deserialize the rest of a sequence whose first element was `version`, upgrading as needed
+    fn from_seq<'de, A: SeqAccess<'de>>(version: Version, seq: A) -> Result<Self, A::Error>;
+
+    /// Deserialize the value of a map entry whose key gave `version`, upgrading as needed
+    fn from_entry<'de, A: MapAccess<'de>>(version: Version, map: &mut A) -> Result<Self, A::Error>;
+}
+
+/// Implemented for `#[version]` types with a `frame`, so they can be the `previous` of another one.
+#[cfg(feature = "bincode")]
+pub trait Framed: Sized {
+    /// Decode a body framed with `version` with `codec`, upgrading as needed
+    fn decode_body<C: crate::Codec, R: std::io::Read>(
+        codec: &C,
+        version: Version,
+        reader: R,
+    ) -> Result<Self, crate::FrameError>;
+}
+
+/// Wraps the items generated for `frame`, which need the `bincode` feature
+#[cfg(feature = "bincode")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __framed {
+    ($($item:item)*) => {
+        $($item)*
+    };
+}
+
+/// Wraps the items generated for `frame`, which need the `bincode` feature
+#[cfg(not(feature = "bincode"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __framed {
+    ($($item:item)*) => {
+        compile_error!(
+            "`frame` needs the `bincode` feature of serde-versions: \
+             serde-versions = { version = \"...\", features = [\"bincode\"] }"
+        );
+    };
+}
+
+pub use crate::__framed as framed;
+
+/// Implemented for `#[version]` types that have a `previous` version.
+pub trait Upgrade: Sized {
+    type Previous;
+
+    /// Convert the previous version into this one
+    fn upgrade(previous: Self::Previous) -> Self;
+}
+
+/// Deserializes any version known to `T` and upgrades it to `T`.
+///
+/// Used as `#[serde(from = "Migrating<T>")]` for types with a `previous` version.
+pub struct Migrating<T>(pub T);
+
+impl<'de, T: Migrate> Deserialize<'de> for Migrating<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if !deserializer.is_human_readable() {
+            return deserialize_stream(deserializer);
+        }
+        let content = Content::deserialize(deserializer)?;
+        Self::from_buffered(content)
+    }
+}
+
+impl<T: Migrate> ReadRest for Migrating<T> {
+    const STREAM: Stream = T::STREAM;
+
+    fn from_seq<'de, A: SeqAccess<'de>>(version: Version, seq: A) -> Result<Self, A::Error> {
+        T::from_seq(version, seq).map(Migrating)
+    }
+
+    fn from_entry<'de, A: MapAccess<'de>>(version: Version, map: &mut A) -> Result<Self, A::Error> {
+        T::from_entry(version, map).map(Migrating)
+    }
+
+    fn from_buffered<E: Error>(content: Content) -> Result<Self, E> {
+        let (version, content) = versioned_content::<T, _>(content)?;
+        T::from_content(version, content).map(Migrating)
+    }
+}
+
+/// The version of a buffered payload, falling back to the one assumed by `missing` for payloads
+/// written without a version
+pub fn versioned_content<T: Migrate, E: Error>(content: Content) -> Result<(Version, Content), E> {
+    match T::version_of(&content) {
+        Ok(version) => Ok((version, content)),
+        Err(err) => T::assume_version(content).map_err(|_| err),
+    }
+}
+
+/// How a payload written without a version is given one, following the layout
+#[derive(Clone, Copy)]
+pub enum Legacy {
+    /// The version field is added, in the flat layout
+    Field(&'static str),
+    /// The payload is put in an envelope, in the envelope layout
+    Envelope {
+        field: &'static str,
+        content: &'static str,
+    },
+    /// The payload is put under a version key, in the keyed layout
+    Keyed(KeyFormat, Repr),
+}
+
+/// Give a buffered payload written without a version the given one, the way the type writes it.
+/// Gives the payload back if it has a version
+pub fn assume_version(
+    content: Content,
+    version: &Version,
+    legacy: Legacy,
+) -> Result<(Version, Content), Content> {
+    let (value, text) = match version {
+        Version::Number(number) => (Value::U64(*number), number.to_string()),
+        Version::Text(text) => (Value::String(text.to_string()), text.to_string()),
+        Version::Semver { .. } => {
+            let text = version.to_string();
+            (Value::String(text.clone()), text)
+        }
+    };
+    let content = match (legacy, content) {
+        (Legacy::Field(field), Value::Map(mut map)) => {
+            let field = Value::String(field.to_owned());
+            if map.contains_key(&field) {
+                return Err(Value::Map(map));
+            }
+            map.insert(field, value);
+            Value::Map(map)
+        }
+        (
+            Legacy::Envelope {
+                field,
+                content: key,
+            },
+            content,
+        ) => {
+            if let Value::Map(map) = &content {
+                if map.contains_key(&Value::String(field.to_owned())) {
+                    return Err(content);
+                }
+            }
+            let mut envelope = BTreeMap::new();
+            envelope.insert(Value::String(field.to_owned()), value);
+            envelope.insert(Value::String(key.to_owned()), content);
+            Value::Map(envelope)
+        }
+        (Legacy::Keyed(key, repr), content) => {
+            if keyed_version::<serde::de::value::Error>(&content, key, repr).is_ok() {
+                return Err(content);
+            }
+            let mut keyed = BTreeMap::new();
+            keyed.insert(Value::String(key.format(text)), content);
+            Value::Map(keyed)
+        }
+        (_, content) => return Err(content),
+    };
+    Ok((version.clone(), content))
+}
+
+/// Read the version out of a buffered named (map) or tuple (seq) payload
+pub fn content_version<E: Error>(content: &Content, field: &str, repr: Repr) -> Result<Version, E> {
+    let version = match content {
+        Value::Map(map) => map.get(&Value::String(field.to_owned())),
+        Value::Seq(seq) => seq.first(),
+        _ => None,
+    };
+    match version {
+        Some(version) => repr.deserialize(ValueDeserializer::<E>::new(version.clone())),
+        None => Err(VersionError::Missing.into_de_error()),
+    }
+}
+
+/// The old key of a renamed field, read from payloads older than `before`
+pub struct Rename {
+    pub from: &'static str,
+    pub before: u64,
+}
+
+/// A field removed by `before`, still carried by older payloads. `read` checks its value
+pub struct Removed<E> {
+    pub name: &'static str,
+    pub before: u64,
+    pub read: fn(Content) -> Result<(), E>,
+}
+
+/// A field written from version `since` and before version `until`
+pub struct Presence {
+    pub name: &'static str,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+/// Where the fields are in a buffered payload, following the layout
+#[derive(Clone, Copy)]
+pub enum FieldsAt {
+    /// Next to the version, in the flat layout
+    Top,
+    /// Under the content key, in the envelope layout
+    Under(&'static str),
+    /// Under the version key, in the keyed layout
+    Keyed,
+}
+
+/// Drop the old keys of renamed fields from a buffered payload written after they were renamed,
+/// so they are only read from the versions they belong to
+pub fn drop_renamed(content: &mut Content, version: &Version, renames: &[Rename], at: FieldsAt) {
+    let version = match version {
+        Version::Number(version) => *version,
+        _ => return,
+    };
+    if let Some(fields) = fields_at(content, at) {
+        for rename in renames.iter().filter(|rename| version >= rename.before) {
+            fields.remove(&Value::String(rename.from.to_owned()));
+        }
+    }
+}
+
+/// Drop removed fields from a buffered payload written before they were removed, and reject
+/// payloads written after that still carry them
+pub fn drop_removed<E: Error>(
+    content: &mut Content,
+    version: &Version,
+    removed: &[Removed<E>],
+    at: FieldsAt,
+) -> Result<(), E> {
+    let version = match version {
+        Version::Number(version) => *version,
+        _ => return Ok(()),
+    };
+    if let Some(fields) = fields_at(content, at) {
+        for field in removed {
+            match fields.remove(&Value::String(field.name.to_owned())) {
+                Some(value) if version < field.before => (field.read)(value)?,
+                Some(_) => {
+                    return Err(E::custom(format_args!(
+                        "field `{}` was removed in version {}",
+                        field.name, field.before
+                    )))
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reject buffered payloads carrying a field their version doesn't write, which would otherwise
+/// be ignored and filled in
+pub fn reject_absent<E: Error>(
+    content: &mut Content,
+    version: &Version,
+    presence: &[Presence],
+    at: FieldsAt,
+) -> Result<(), E> {
+    let version = match version {
+        Version::Number(version) => *version,
+        _ => return Ok(()),
+    };
+    if let Some(fields) = fields_at(content, at) {
+        for field in presence {
+            if !fields.contains_key(&Value::String(field.name.to_owned())) {
+                continue;
+            }
+            match (field.since, field.until) {
+                (Some(since), _) if version < since => {
+                    return Err(E::custom(format_args!(
+                        "field `{}` was added in version {}",
+                        field.name, since
+                    )))
+                }
+                (_, Some(until)) if version >= until => {
+                    return Err(E::custom(format_args!(
+                        "field `{}` was removed in version {}",
+                        field.name, until
+                    )))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The map of fields in a buffered payload
+fn fields_at(content: &mut Content, at: FieldsAt) -> Option<&mut BTreeMap<Value, Value>> {
+    let fields = match (at, content) {
+        (FieldsAt::Top, fields) => Some(fields),
+        (FieldsAt::Under(key), Value::Map(map)) => map.get_mut(&Value::String(key.to_owned())),
+        (FieldsAt::Keyed, Value::Map(map)) => map.values_mut().next(),
+        _ => None,
+    };
+    match fields {
+        Some(Value::Map(fields)) => Some(fields),
+        _ => None,
+    }
+}
+
+/// `a == b` usable in constants, to check the versions listed in `versions(...)`
+pub const fn version_eq(a: &Version, b: &Version) -> bool {
+    match (a, b) {
+        (Version::Number(a), Version::Number(b)) => *a == *b,
+        (
+            Version::Semver {
+                major,
+                minor,
+                patch,
+            },
+            Version::Semver {
+                major: b_major,
+                minor: b_minor,
+                patch: b_patch,
+            },
+        ) => *major == *b_major && *minor == *b_minor && *patch == *b_patch,
+        (Version::Text(a), Version::Text(b)) => {
+            let (a, b) = match (a, b) {
+                (Cow::Borrowed(a), Cow::Borrowed(b)) => (a.as_bytes(), b.as_bytes()),
+                _ => return false,
+            };
+            if a.len() != b.len() {
+                return false;
+            }
+            let mut i = 0;
+            while i < a.len() {
+                if a[i] != b[i] {
+                    return false;
+                }
+                i += 1;
+            }
+            true
+        }
+        _ => false,
+    }
+}
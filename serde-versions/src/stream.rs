@@ -0,0 +1,194 @@
+//! Reading older versions from formats that aren't human readable, such as bincode.
+//!
+//! Those formats can't be buffered, as that needs `deserialize_any`, but they write fields in
+//! order, so the version is the first element of a struct and the key of a keyed value. It is read
+//! first and the rest of the payload is read straight into the type that writes that version.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_value::Value;
+
+use crate::__private::{Content, KeyFormat, Repr};
+use crate::de::EnumSeed;
+use crate::envelope::{DataSeed, DeserializeData};
+use crate::{Version, VersionError};
+
+/// How a type's payload starts, for reading its version ahead of the rest
+#[derive(Clone, Copy)]
+pub struct Stream {
+    pub type_name: &'static str,
+    /// The version is read as the `repr` of the newest type, older types have to share it
+    pub repr: Repr,
+    /// Set for the keyed layout, whose version is in the key of a map
+    pub key: Option<KeyFormat>,
+    /// The most elements a struct payload of any known version has, the version included
+    pub len: usize,
+}
+
+/// `a.max(b)`, usable in constants to size [`Stream::len`] over a chain of versions
+pub const fn max_len(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// A type read by [`deserialize_stream`], given its version
+pub trait ReadRest: Sized {
+    const STREAM: Stream;
+
+    /// Read the rest of a sequence whose first element was `version`
+    fn from_seq<'de, A: SeqAccess<'de>>(version: Version, seq: A) -> Result<Self, A::Error>;
+
+    /// Read the value of the map entry whose key gave `version`
+    fn from_entry<'de, A: MapAccess<'de>>(version: Version, map: &mut A) -> Result<Self, A::Error>;
+
+    /// Read a payload that had to be buffered after all, e.g. a map in a self-describing format
+    fn from_buffered<E: Error>(content: Content) -> Result<Self, E>;
+}
+
+/// Names standing in for the fields of a struct payload: formats that aren't self-describing only
+/// use how many there are
+static PLACEHOLDERS: [&str; 256] = [""; 256];
+
+/// Deserialize `R` from a format that isn't human readable, reading the version before the rest
+pub fn deserialize_stream<'de, R: ReadRest, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<R, D::Error> {
+    let stream = R::STREAM;
+    let visitor = StreamVisitor {
+        stream,
+        marker: PhantomData,
+    };
+    match stream.key {
+        Some(_) => deserializer.deserialize_map(visitor),
+        None => {
+            let fields = &PLACEHOLDERS[..stream.len.min(PLACEHOLDERS.len())];
+            deserializer.deserialize_struct(stream.type_name, fields, visitor)
+        }
+    }
+}
+
+struct StreamVisitor<R> {
+    stream: Stream,
+    marker: PhantomData<R>,
+}
+
+impl<'de, R: ReadRest> Visitor<'de> for StreamVisitor<R> {
+    type Value = R;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a versioned {}", self.stream.type_name)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<R, A::Error> {
+        match seq.next_element_seed(ReprSeed(self.stream.repr))? {
+            Some(version) => R::from_seq(version, seq),
+            None => Err(VersionError::Missing.into_de_error()),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<R, A::Error> {
+        let mut entries = BTreeMap::new();
+        if let Some(key) = self.stream.key {
+            match map.next_key::<String>()? {
+                Some(found) => match key.parse(&found, self.stream.repr) {
+                    Some(version) => {
+                        let value = R::from_entry(version, &mut map)?;
+                        if map.next_key::<IgnoredAny>()?.is_some() {
+                            return Err(A::Error::invalid_length(2, &"a single version key"));
+                        }
+                        return Ok(value);
+                    }
+                    // e.g. a payload written without a version, read as `missing` says
+                    None => {
+                        entries.insert(Value::String(found), map.next_value()?);
+                    }
+                },
+                None => return R::from_buffered(Value::Map(entries)),
+            }
+        }
+        while let Some((key, value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        R::from_buffered(Value::Map(entries))
+    }
+}
+
+/// Deserializes a version written as the given `repr`
+struct ReprSeed(Repr);
+
+impl<'de> DeserializeSeed<'de> for ReprSeed {
+    type Value = Version;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Version, D::Error> {
+        self.0.deserialize(deserializer)
+    }
+}
+
+/// Hands the elements left after the version to a derived visitor, as the fields of the struct or
+/// as the enum
+pub struct SeqRest<A>(A);
+
+impl<A> SeqRest<A> {
+    pub fn new(seq: A) -> Self {
+        SeqRest(seq)
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> Deserializer<'de> for SeqRest<A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_seq(self.0)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        // an enum is written as the element after the version
+        EnumSeed {
+            name,
+            variants,
+            visitor,
+        }
+        .next_of(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit newtype_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+/// Read the element after the version as a value with its own layout, in the envelope layout
+pub fn next_data<'de, T: DeserializeData<'de>, A: SeqAccess<'de>>(
+    mut seq: A,
+) -> Result<T, A::Error> {
+    seq.next_element_seed(DataSeed(PhantomData))?
+        .ok_or_else(|| A::Error::invalid_length(1, &"a version followed by the data"))
+}
+
+/// Read the value under the version key as a value with its own layout, in the keyed layout
+pub fn entry_data<'de, T: DeserializeData<'de>, A: MapAccess<'de>>(
+    map: &mut A,
+) -> Result<T, A::Error> {
+    map.next_value_seed(DataSeed(PhantomData))
+}
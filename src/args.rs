@@ -0,0 +1,756 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, Ident, LitInt, LitStr, Path, Token, Type};
+
+/// Arguments of the `#[version(...)]` attribute.
+///
+/// e.g. `#[version(3, previous = SV2, upgrade = upgrade_fn)]` or `#[version(3, versions(1 = SV1, 2 = SV2))]`
+pub(crate) struct VersionArgs {
+    /// The version written into the payload
+    pub version: VersionLit,
+    /// The type the version is written as. Defaults to `u8`, or a string for string versions
+    pub repr: Repr,
+    /// Where the version goes relative to the value
+    pub layout: Layout,
+    /// Which payload versions are read. Only semantic versions can accept more than their own
+    pub compat: Compat,
+    /// How the version header of `encode_versioned` is written, if the type is framed
+    pub frame: Option<Frame>,
+    /// Name of the version field, both serialized and in the generated structs. Defaults to `version`
+    pub field: Ident,
+    /// The type describing the previous version, if any
+    pub previous: Option<Type>,
+    /// Function converting `previous` into this type. Defaults to `From::from`
+    pub upgrade: Option<Path>,
+    /// Every older version, used to generate the enum of all known versions
+    pub versions: Vec<KnownVersion>,
+    /// Keys renamed with `#[versioned(renamed_from = "...", before = N)]` on the fields
+    pub renames: Vec<Rename>,
+    /// Fields whose type changed, given with `#[versioned(before = N, as = "...")]`
+    pub conversions: Vec<Conversion>,
+    /// Fields deleted from the type, given with `removed(name: Type, before = N)`
+    pub removed: Vec<Removed>,
+    /// Fields only some versions write, given with `#[versioned(since = N, until = M)]`
+    pub presence: Vec<Presence>,
+    /// The version assumed for payloads written without one
+    pub missing: Option<VersionLit>,
+    /// Set by `forward`: newer versions are read too, keeping the fields they add in `extra`
+    pub forward: Option<Ident>,
+    /// The newest numbered version read, given with `forward = N`
+    pub newest: Option<u64>,
+    /// Set by `downgrade`: the type can be written as `previous`, converted with `TryFrom`
+    pub downgrade: Option<Ident>,
+}
+
+/// An old key of a field, read from payloads older than `before`
+pub(crate) struct Rename {
+    pub from: LitStr,
+    pub before: u64,
+}
+
+/// A field read as `old` from payloads older than `before`, then converted to its type
+pub(crate) struct Conversion {
+    pub field: Ident,
+    pub ty: Type,
+    pub old: Type,
+    /// Function converting `old` into the field's type. Defaults to `From::from`
+    pub convert: Option<Path>,
+    pub before: u64,
+}
+
+/// A field older payloads still carry, read as `ty` and dropped, from payloads older than `before`
+pub(crate) struct Removed {
+    pub name: Ident,
+    pub ty: Type,
+    pub before: u64,
+}
+
+/// A field written from version `since` and before version `until`, filled in with `default` when
+/// a payload lacks it
+pub(crate) struct Presence {
+    pub name: Ident,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    /// Function filling in the field. Defaults to `Default::default`
+    pub default: Option<LitStr>,
+}
+
+impl Presence {
+    /// Whether payloads of `version` carry the field
+    pub fn written_in(&self, version: u64) -> bool {
+        self.since.map_or(true, |since| since <= version)
+            && self.until.map_or(true, |until| version < until)
+    }
+}
+
+/// An entry of `versions(...)` e.g. `1 = SV1`
+pub(crate) struct KnownVersion {
+    pub version: VersionLit,
+    pub ty: Type,
+}
+
+/// A version as written in the attribute, e.g. `3` or `"2024-01"`
+pub(crate) enum VersionLit {
+    /// Kept without any suffix so it can be used with whichever `repr`
+    Number(LitInt),
+    Text(LitStr),
+    /// A string of the form `MAJOR.MINOR.PATCH`
+    Semver {
+        lit: LitStr,
+        major: u64,
+        minor: u64,
+        patch: u64,
+    },
+}
+
+impl VersionLit {
+    /// The version as part of an identifier, e.g. `3` in `_Sv3` or `2024_01` in `_Sv2024_01`
+    pub fn ident_suffix(&self) -> String {
+        match self {
+            VersionLit::Number(lit) => lit.base10_digits().to_owned(),
+            VersionLit::Text(lit) | VersionLit::Semver { lit, .. } => lit
+                .value()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect(),
+        }
+    }
+
+    /// Expression building the `serde_versions::Version` of this literal
+    pub fn to_version(&self) -> TokenStream {
+        match self {
+            VersionLit::Number(lit) => quote!(::serde_versions::Version::Number(#lit)),
+            VersionLit::Text(lit) => {
+                quote!(::serde_versions::Version::Text(::std::borrow::Cow::Borrowed(#lit)))
+            }
+            VersionLit::Semver {
+                major,
+                minor,
+                patch,
+                ..
+            } => quote! {
+                ::serde_versions::Version::Semver { major: #major, minor: #minor, patch: #patch }
+            },
+        }
+    }
+}
+
+impl ToTokens for VersionLit {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            VersionLit::Number(lit) => lit.to_tokens(tokens),
+            VersionLit::Text(lit) | VersionLit::Semver { lit, .. } => lit.to_tokens(tokens),
+        }
+    }
+}
+
+impl Parse for VersionLit {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            let lit: LitStr = input.parse()?;
+            if lit.value().is_empty() {
+                return Err(syn::Error::new(lit.span(), "version can't be empty"));
+            }
+            return Ok(match parse_semver(&lit.value()) {
+                Some((major, minor, patch)) => VersionLit::Semver {
+                    lit,
+                    major,
+                    minor,
+                    patch,
+                },
+                None => VersionLit::Text(lit),
+            });
+        }
+        if !input.peek(LitInt) {
+            return Err(input
+                .error("expected a version, e.g. `#[version(3)]` or `#[version(\"2024-01\")]`"));
+        }
+        let lit: LitInt = input.parse()?;
+        let number = lit.base10_parse::<u64>().map_err(|_| {
+            syn::Error::new(
+                lit.span(),
+                format!("version `{}` does not fit in a u64", lit.base10_digits()),
+            )
+        })?;
+        Ok(VersionLit::Number(LitInt::new(
+            &number.to_string(),
+            lit.span(),
+        )))
+    }
+}
+
+/// Split `MAJOR.MINOR.PATCH` into its numbers
+fn parse_semver(text: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = text.split('.').map(|part| {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            None
+        } else {
+            part.parse::<u64>().ok()
+        }
+    });
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+            Some((major, minor, patch))
+        }
+        _ => None,
+    }
+}
+
+/// The type a version is written as, chosen with `repr`
+#[derive(Clone, Copy)]
+pub(crate) enum Repr {
+    U8,
+    U16,
+    U32,
+    U64,
+    Str,
+    Semver,
+}
+
+impl Repr {
+    /// Type of the version field in the owned versioned struct
+    pub fn ty(self) -> TokenStream {
+        match self {
+            Repr::U8 => quote!(u8),
+            Repr::U16 => quote!(u16),
+            Repr::U32 => quote!(u32),
+            Repr::U64 => quote!(u64),
+            Repr::Str | Repr::Semver => quote!(::std::string::String),
+        }
+    }
+
+    /// Type of the version field in the borrowing `Ref` mirrors
+    pub fn ref_ty(self) -> TokenStream {
+        match self {
+            Repr::Str | Repr::Semver => quote!(&'static str),
+            _ => self.ty(),
+        }
+    }
+
+    /// The matching `serde_versions::__private::Repr`
+    pub fn to_runtime(self) -> TokenStream {
+        let variant = match self {
+            Repr::U8 => quote!(U8),
+            Repr::U16 => quote!(U16),
+            Repr::U32 => quote!(U32),
+            Repr::U64 => quote!(U64),
+            Repr::Str => quote!(Str),
+            Repr::Semver => quote!(Semver),
+        };
+        quote!(::serde_versions::__private::Repr::#variant)
+    }
+
+    /// Function turning the version field of the owned versioned struct into a `Version`
+    pub fn into_version(self) -> TokenStream {
+        match self {
+            Repr::Semver => quote!(::serde_versions::__private::semver_or_text),
+            _ => quote!(::serde_versions::Version::from),
+        }
+    }
+
+    /// The largest version number this repr can hold
+    fn max(self) -> u64 {
+        match self {
+            Repr::U8 => u8::MAX.into(),
+            Repr::U16 => u16::MAX.into(),
+            Repr::U32 => u32::MAX.into(),
+            Repr::U64 | Repr::Str | Repr::Semver => u64::MAX,
+        }
+    }
+}
+
+/// Where the version goes relative to the value, chosen with `repr = "..."`
+pub(crate) enum Layout {
+    /// Next to the value's own fields, e.g. `{ "version": 3, "i": 0 }`
+    Flat,
+    /// Wrapping the value under a content key, e.g. `{ "version": 3, "data": { "i": 0 } }`
+    Envelope { content: Ident },
+    /// As the only key of a map, formatted with `key`, e.g. `{ "v3": { "i": 0 } }`
+    Keyed { key: KeyFormat },
+}
+
+enum LayoutKind {
+    Flat,
+    Envelope,
+    Keyed,
+}
+
+/// The format of the key naming the version in the keyed layout, e.g. `"v{}"`
+pub(crate) struct KeyFormat {
+    prefix: String,
+    suffix: String,
+}
+
+impl KeyFormat {
+    /// The key naming the given version, e.g. `"v3"`
+    pub fn format(&self, version: &VersionLit) -> String {
+        let version = match version {
+            VersionLit::Number(lit) => lit.base10_digits().to_owned(),
+            VersionLit::Text(lit) | VersionLit::Semver { lit, .. } => lit.value(),
+        };
+        format!("{}{}{}", self.prefix, version, self.suffix)
+    }
+
+    /// The matching `serde_versions::__private::KeyFormat`
+    pub fn to_runtime(&self) -> TokenStream {
+        let KeyFormat { prefix, suffix } = self;
+        quote!(::serde_versions::__private::KeyFormat { prefix: #prefix, suffix: #suffix })
+    }
+}
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        KeyFormat {
+            prefix: "v".to_owned(),
+            suffix: String::new(),
+        }
+    }
+}
+
+/// Parse a key format, which has to contain `{}` once, e.g. `"v{}"` in `key = "v{}"`
+fn parse_key_format(format: &LitStr) -> syn::Result<KeyFormat> {
+    let value = format.value();
+    match value.split_once("{}") {
+        Some((prefix, suffix)) if !suffix.contains("{}") => Ok(KeyFormat {
+            prefix: prefix.to_owned(),
+            suffix: suffix.to_owned(),
+        }),
+        _ => Err(syn::Error::new(
+            format.span(),
+            "`key` has to contain `{}` exactly once, e.g. \"v{}\"",
+        )),
+    }
+}
+
+/// Parse a string naming a field, e.g. `"schema_version"` in `field = "schema_version"`
+fn parse_ident(name: LitStr, arg: &str) -> syn::Result<Ident> {
+    let ident = syn::parse_str::<Ident>(&name.value()).map_err(|_| {
+        syn::Error::new(
+            name.span(),
+            format!(
+                "`{}` has to be a valid identifier, found {:?}",
+                arg,
+                name.value()
+            ),
+        )
+    })?;
+    Ok(Ident::new(&ident.to_string(), name.span()))
+}
+
+/// Which payload versions a type reads, chosen with `compat`
+#[derive(Clone, Copy)]
+pub(crate) enum Compat {
+    Exact,
+    Major,
+    Minor,
+    /// Any older number, set when fields describe their history with `#[versioned]`
+    Older,
+}
+
+impl Compat {
+    /// The matching `serde_versions::__private::Compat`
+    pub fn to_runtime(self) -> TokenStream {
+        let variant = match self {
+            Compat::Exact => quote!(Exact),
+            Compat::Major => quote!(Major),
+            Compat::Minor => quote!(Minor),
+            Compat::Older => quote!(Older),
+        };
+        quote!(::serde_versions::__private::Compat::#variant)
+    }
+}
+
+/// How the version header is written in front of the value, chosen with `frame`
+#[derive(Clone, Copy)]
+pub(crate) enum Frame {
+    Varint,
+    Fixed,
+}
+
+impl Frame {
+    /// The matching `serde_versions::__private::Header`
+    pub fn to_runtime(self) -> TokenStream {
+        let variant = match self {
+            Frame::Varint => quote!(Varint),
+            Frame::Fixed => quote!(Fixed),
+        };
+        quote!(::serde_versions::__private::Header::#variant)
+    }
+}
+
+impl Parse for KnownVersion {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let version = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let ty = input.parse()?;
+        Ok(KnownVersion { version, ty })
+    }
+}
+
+/// The contents of `removed(...)`, e.g. `legacy_flag: bool, before = 5`
+fn parse_removed(input: ParseStream, span: Span) -> syn::Result<Vec<Removed>> {
+    let mut fields = Vec::new();
+    let mut before = None;
+    while !input.is_empty() {
+        let name: Ident = input.parse()?;
+        if name == "before" && input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            let lit: LitInt = input.parse()?;
+            before = Some(lit.base10_parse::<u64>()?);
+        } else {
+            input.parse::<Token![:]>()?;
+            fields.push((name, input.parse::<Type>()?));
+        }
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    let before = before.ok_or_else(|| {
+        syn::Error::new(
+            span,
+            "`removed` needs `before`, the first version without the fields",
+        )
+    })?;
+    if fields.is_empty() {
+        return Err(syn::Error::new(
+            span,
+            "`removed` needs the fields, e.g. `removed(flag: bool, before = 2)`",
+        ));
+    }
+    Ok(fields
+        .into_iter()
+        .map(|(name, ty)| Removed { name, ty, before })
+        .collect())
+}
+
+impl Parse for VersionArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let version: VersionLit = input.parse()?;
+        let mut repr = None;
+        let mut layout = None;
+        let mut content = None;
+        let mut key_format = None;
+        let mut compat = None;
+        let mut frame = None;
+        let mut field = None;
+        let mut previous = None;
+        let mut upgrade = None;
+        let mut versions = Vec::new();
+        let mut removed = Vec::new();
+        let mut missing = None;
+        let mut forward = None;
+        let mut newest = None;
+        let mut downgrade = None;
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            match key.to_string().as_str() {
+                "field" => {
+                    input.parse::<Token![=]>()?;
+                    field = Some(parse_ident(input.parse()?, "field")?);
+                }
+                "content" => {
+                    input.parse::<Token![=]>()?;
+                    content = Some(parse_ident(input.parse()?, "content")?);
+                }
+                "key" => {
+                    input.parse::<Token![=]>()?;
+                    let format: LitStr = input.parse()?;
+                    key_format = Some((parse_key_format(&format)?, format));
+                }
+                "repr" if input.peek2(LitStr) => {
+                    input.parse::<Token![=]>()?;
+                    let name: LitStr = input.parse()?;
+                    layout = Some(match name.value().as_str() {
+                        "flat" => (LayoutKind::Flat, name),
+                        "envelope" => (LayoutKind::Envelope, name),
+                        "keyed" => (LayoutKind::Keyed, name),
+                        _ => return Err(syn::Error::new(
+                            name.span(),
+                            "`repr` has to be an integer type, \"flat\", \"envelope\" or \"keyed\"",
+                        )),
+                    });
+                }
+                "repr" => {
+                    input.parse::<Token![=]>()?;
+                    let ty: Ident = input.parse()?;
+                    repr = Some(match ty.to_string().as_str() {
+                        "u8" => (Repr::U8, ty),
+                        "u16" => (Repr::U16, ty),
+                        "u32" => (Repr::U32, ty),
+                        "u64" => (Repr::U64, ty),
+                        _ => {
+                            return Err(syn::Error::new(
+                                ty.span(),
+                                "`repr` has to be one of `u8`, `u16`, `u32` or `u64`",
+                            ))
+                        }
+                    });
+                }
+                "compat" => {
+                    input.parse::<Token![=]>()?;
+                    let rule: LitStr = input.parse()?;
+                    compat = Some(match rule.value().as_str() {
+                        "exact" => (Compat::Exact, rule),
+                        "major" => (Compat::Major, rule),
+                        "minor" => (Compat::Minor, rule),
+                        _ => {
+                            return Err(syn::Error::new(
+                                rule.span(),
+                                "`compat` has to be one of \"exact\", \"major\" or \"minor\"",
+                            ))
+                        }
+                    });
+                }
+                "frame" => {
+                    input.parse::<Token![=]>()?;
+                    let header: LitStr = input.parse()?;
+                    frame = Some(match header.value().as_str() {
+                        "varint" => Frame::Varint,
+                        "fixed" => Frame::Fixed,
+                        _ => {
+                            return Err(syn::Error::new(
+                                header.span(),
+                                "`frame` has to be \"varint\" or \"fixed\"",
+                            ))
+                        }
+                    });
+                }
+                "previous" => {
+                    input.parse::<Token![=]>()?;
+                    previous = Some(input.parse()?);
+                }
+                "upgrade" => {
+                    input.parse::<Token![=]>()?;
+                    upgrade = Some(input.parse()?);
+                }
+                "forward" => {
+                    if input.peek(Token![=]) {
+                        input.parse::<Token![=]>()?;
+                        newest = Some(input.parse::<LitInt>()?);
+                    }
+                    forward = Some(key);
+                }
+                "downgrade" => downgrade = Some(key),
+                "missing" => {
+                    input.parse::<Token![=]>()?;
+                    missing = Some(input.parse()?);
+                }
+                "removed" => {
+                    let content;
+                    parenthesized!(content in input);
+                    let span = key.span();
+                    let fields = parse_removed(&content, span)?;
+                    removed.push((fields, span));
+                }
+                "versions" => {
+                    let content;
+                    parenthesized!(content in input);
+                    versions = Punctuated::<KnownVersion, Token![,]>::parse_terminated(&content)?
+                        .into_iter()
+                        .collect();
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("unknown `version` argument `{}`", key),
+                    ))
+                }
+            }
+        }
+
+        // the newest of the listed versions is the one this type upgrades from
+        if previous.is_none() {
+            previous = versions.last().map(|v: &KnownVersion| v.ty.clone());
+        }
+
+        if upgrade.is_some() && previous.is_none() {
+            return Err(input.error("`upgrade` requires `previous`"));
+        }
+        if let (Some(downgrade), None) = (&downgrade, &previous) {
+            return Err(syn::Error::new(
+                downgrade.span(),
+                "`downgrade` requires `previous`, the type to convert to",
+            ));
+        }
+
+        let repr = match (&version, repr) {
+            (VersionLit::Text(_), None) => Repr::Str,
+            (VersionLit::Semver { .. }, None) => Repr::Semver,
+            (VersionLit::Text(_), Some((_, ty))) | (VersionLit::Semver { .. }, Some((_, ty))) => {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    "string versions can't have a numeric `repr`",
+                ))
+            }
+            (VersionLit::Number(lit), repr) => {
+                let repr = repr.map_or(Repr::U8, |(repr, _)| repr);
+                if lit.base10_parse::<u64>()? > repr.max() {
+                    let ty = repr.ty();
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        format!(
+                            "version `{}` does not fit in {}, choose a wider type with `repr`",
+                            lit, ty
+                        ),
+                    ));
+                }
+                repr
+            }
+        };
+
+        // only newer versions that keep the fields of this one are read
+        let newest = match (&version, &forward, newest) {
+            (VersionLit::Text(_), Some(forward), _) => {
+                return Err(syn::Error::new(
+                    forward.span(),
+                    "`forward` needs a numeric or semantic version to tell newer versions apart",
+                ));
+            }
+            (VersionLit::Number(_), Some(forward), None) => {
+                return Err(syn::Error::new(
+                    forward.span(),
+                    "`forward` needs the newest version to read, e.g. `forward = 5`",
+                ));
+            }
+            (VersionLit::Number(lit), Some(_), Some(newest)) => {
+                let current = lit.base10_parse::<u64>()?;
+                let number = newest.base10_parse::<u64>()?;
+                if number <= current {
+                    return Err(syn::Error::new(
+                        newest.span(),
+                        format!("`forward` has to name a version newer than {}", current),
+                    ));
+                }
+                Some(number)
+            }
+            (VersionLit::Semver { .. }, Some(_), Some(newest)) => {
+                return Err(syn::Error::new(
+                    newest.span(),
+                    "`forward` reads the newer versions with the same major version, without a bound",
+                ));
+            }
+            _ => None,
+        };
+
+        // the versions before a removal still carry the field, so they are read too
+        let removed = match &version {
+            VersionLit::Number(lit) => {
+                let current = lit.base10_parse::<u64>()?;
+                let mut all = Vec::new();
+                for (fields, span) in removed {
+                    if fields[0].before > current {
+                        return Err(syn::Error::new(
+                            span,
+                            format!("`before` can't be newer than version {}", current),
+                        ));
+                    }
+                    all.extend(fields);
+                }
+                all
+            }
+            _ => match removed.first() {
+                Some((_, span)) => {
+                    return Err(syn::Error::new(*span, "`removed` needs a numeric version"))
+                }
+                None => Vec::new(),
+            },
+        };
+
+        // semantic versions read older minor versions by default, anything else only itself
+        let compat = match (&version, compat) {
+            (VersionLit::Semver { .. }, compat) => {
+                compat.map_or(Compat::Minor, |(compat, _)| compat)
+            }
+            (_, None) if !removed.is_empty() => Compat::Older,
+            (_, None) => Compat::Exact,
+            (_, Some((_, rule))) => {
+                return Err(syn::Error::new(
+                    rule.span(),
+                    "`compat` needs a semantic version, e.g. `#[version(\"1.4.0\")]`",
+                ))
+            }
+        };
+
+        let keyed = matches!(layout, Some((LayoutKind::Keyed, _)));
+        if let (Some((_, format)), false) = (&key_format, keyed) {
+            return Err(syn::Error::new(
+                format.span(),
+                "`key` requires `repr = \"keyed\"`",
+            ));
+        }
+        if let (true, Some(field)) = (keyed, &field) {
+            return Err(syn::Error::new(
+                field.span(),
+                "the keyed layout has no version field, use `key` to format the version key",
+            ));
+        }
+
+        let field = field.unwrap_or_else(|| Ident::new("version", Span::call_site()));
+        let layout = match (layout, content) {
+            (Some((LayoutKind::Keyed, _)), None) => Layout::Keyed {
+                key: key_format.map_or_else(KeyFormat::default, |(format, _)| format),
+            },
+            (Some((LayoutKind::Envelope, _)), content) => {
+                let content = content.unwrap_or_else(|| Ident::new("data", Span::call_site()));
+                if content == field {
+                    return Err(syn::Error::new(
+                        content.span(),
+                        "`content` has to differ from the version field",
+                    ));
+                }
+                Layout::Envelope { content }
+            }
+            (_, Some(content)) => {
+                return Err(syn::Error::new(
+                    content.span(),
+                    "`content` requires `repr = \"envelope\"`",
+                ))
+            }
+            (_, None) => Layout::Flat,
+        };
+
+        Ok(VersionArgs {
+            version,
+            repr,
+            layout,
+            compat,
+            frame,
+            field,
+            previous,
+            upgrade,
+            versions,
+            renames: Vec::new(),
+            conversions: Vec::new(),
+            removed,
+            presence: Vec::new(),
+            missing,
+            forward,
+            newest,
+            downgrade,
+        })
+    }
+}
+
+impl VersionArgs {
+    /// Whether the value is also written with its own layout, through the `SerializeData` and
+    /// `DeserializeData` impls
+    pub fn data_impls(&self) -> bool {
+        !matches!(self.layout, Layout::Flat) || self.frame.is_some()
+    }
+
+    /// Expression for the version as stored in the owned versioned struct
+    pub fn owned_version(&self) -> TokenStream {
+        let version = &self.version;
+        match self.repr {
+            Repr::Str | Repr::Semver => quote!(::std::string::String::from(#version)),
+            _ => quote!(#version),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Lit, Meta, NestedMeta, Path, Token, WherePredicate};
+
+/// Remove the derive whose last path segment is `name` (e.g. `Serialize` or `serde::Serialize`)
+/// from `attrs`, returning the path it was derived with.
+pub(crate) fn take_derive(attrs: &mut [Attribute], name: &str) -> Option<Path> {
+    let mut found = None;
+    for attr in attrs.iter_mut() {
+        if !attr.path.is_ident("derive") {
+            continue;
+        }
+        let paths = match attr.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated) {
+            Ok(paths) => paths,
+            Err(_) => continue,
+        };
+        let (matching, rest): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| {
+            path.segments
+                .last()
+                .is_some_and(|segment| segment.ident == name)
+        });
+        if let Some(path) = matching.into_iter().next() {
+            *attr = syn::parse_quote!(#[derive(#(#rest),*)]);
+            found = Some(path);
+        }
+    }
+    found
+}
+
+/// Only keep `#[serde(...)]` attributes
+pub(crate) fn retain_serde(attrs: &mut Vec<Attribute>) {
+    attrs.retain(|attr| attr.path.is_ident("serde"));
+}
+
+/// Turn a container `#[serde(default)]` into `#[serde(default = "<path>")]`, returning whether there
+/// was one. The bare form asks the type serde derives for, a mirror or the versioned struct, to
+/// implement `Default` itself, while only the original type does.
+pub(crate) fn default_from(attrs: &mut [Attribute], path: &str) -> bool {
+    let mut found = false;
+    for attr in attrs.iter_mut().filter(|attr| attr.path.is_ident("serde")) {
+        let nested = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => continue,
+        };
+        let is_default =
+            |n: &NestedMeta| matches!(n, NestedMeta::Meta(Meta::Path(p)) if p.is_ident("default"));
+        if !nested.iter().any(is_default) {
+            continue;
+        }
+        let nested = nested.into_iter().map(|n| {
+            if is_default(&n) {
+                syn::parse_quote!(default = #path)
+            } else {
+                n
+            }
+        });
+        *attr = syn::parse_quote!(#[serde(#(#nested),*)]);
+        found = true;
+    }
+    found
+}
+
+/// Remove every `#[serde(...)]` attribute from a type, its fields and its variants
+pub(crate) fn strip_serde(ast: &mut syn::DeriveInput) {
+    ast.attrs.retain(|attr| !attr.path.is_ident("serde"));
+    let strip_fields = |fields: &mut syn::Fields| {
+        for field in fields.iter_mut() {
+            field.attrs.retain(|attr| !attr.path.is_ident("serde"));
+        }
+    };
+    match &mut ast.data {
+        syn::Data::Struct(data) => strip_fields(&mut data.fields),
+        syn::Data::Enum(data) => {
+            for variant in data.variants.iter_mut() {
+                variant.attrs.retain(|attr| !attr.path.is_ident("serde"));
+                strip_fields(&mut variant.fields);
+            }
+        }
+        syn::Data::Union(_) => {}
+    }
+}
+
+/// Find a `#[serde(...)]` argument named one of `names` in `attrs`, e.g. `into` in `#[serde(into = "T")]`
+pub(crate) fn find_serde_arg(attrs: &[Attribute], names: &[&str]) -> Option<Path> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|nested| {
+            let path = match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => name_value.path,
+                NestedMeta::Meta(Meta::Path(path)) => path,
+                _ => return None,
+            };
+            names.iter().any(|name| path.is_ident(name)).then_some(path)
+        })
+}
+
+/// The predicates of a container `#[serde(bound = "...")]` or `#[serde(bound(<which> = "..."))]`,
+/// `which` being `serialize` or `deserialize`. Bounds that don't parse are left to serde, which
+/// reports them on the mirrors keeping the attribute.
+pub(crate) fn serde_bound(attrs: &[Attribute], which: &str) -> Option<Vec<WherePredicate>> {
+    let parse = |lit: &Lit| match lit {
+        Lit::Str(lit) => lit
+            .parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)
+            .ok()
+            .map(|bound| bound.into_iter().collect()),
+        _ => None,
+    };
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|nested| match nested {
+            NestedMeta::Meta(Meta::NameValue(bound)) if bound.path.is_ident("bound") => {
+                parse(&bound.lit)
+            }
+            NestedMeta::Meta(Meta::List(bound)) if bound.path.is_ident("bound") => {
+                bound.nested.iter().find_map(|nested| match nested {
+                    NestedMeta::Meta(Meta::NameValue(bound)) if bound.path.is_ident(which) => {
+                        parse(&bound.lit)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        })
+}
@@ -0,0 +1,861 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Ident, Lifetime, Path, WherePredicate};
+
+use crate::args::{Compat, Conversion, Layout, Removed, Rename, VersionArgs};
+use crate::attrs::{default_from, retain_serde, serde_bound};
+use crate::history::shape_field;
+use crate::ser::turbofish;
+
+/// Generate the `Deserialize` impl of the original type along with what it needs: a
+/// `#[serde(remote)]` mirror that reads the fields straight into the original type, and the
+/// `Migrate` impl used to read older versions.
+///
+/// When the value is also written with its own layout the mirror is generated by the caller, which
+/// also uses it to serialize.
+///
+/// `ast` is the original type, still carrying its serde attributes.
+pub(crate) fn deserialize(
+    ast: &DeriveInput,
+    args: &VersionArgs,
+    deserialize: &Path,
+) -> TokenStream {
+    let name = &ast.ident;
+    let version = &args.version;
+    let field_str = args.field.to_string();
+    let expected = version.to_version();
+    let repr = args.repr.to_runtime();
+    let compat = args.compat.to_runtime();
+    let forward = args.forward.is_some();
+    let newest = option_tokens(args.newest);
+    let remote_name = remote_name(ast, args);
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+    let owned_bound = deserialize_bound(ast, true);
+    let remote_turbofish = turbofish(&ast.generics);
+
+    let mut de_generics = ast.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    let borrowed = borrowed_lifetimes(ast);
+
+    let remote = (!args.data_impls()).then(|| remote(ast, args, &[deserialize]));
+
+    // `before` is set when reading payloads older than it, whose fields still have their old types
+    let read_with = |deserializer: TokenStream, before: Option<u64>| {
+        let read = match &args.layout {
+            Layout::Flat => {
+                let remote_name = match before {
+                    Some(before) => before_name(ast, args, before),
+                    None => remote_name.clone(),
+                };
+                quote! {
+                    static FIELDS: ::serde_versions::__private::Fields = ::serde_versions::__private::Fields::new();
+                    #remote_name #remote_turbofish ::deserialize(
+                        ::serde_versions::__private::VersionedDeserializer::new(#deserializer, check, &FIELDS)
+                    )
+                }
+            }
+            layout => {
+                let (data, unwrap) = match before {
+                    Some(before) => {
+                        let data = format_ident!("{}Data", before_name(ast, args, before));
+                        (quote!(#data #remote_turbofish), quote!(.map(|data| data.0)))
+                    }
+                    None => (quote!(Self), quote!()),
+                };
+                match layout {
+                    Layout::Envelope { content } => {
+                        let content_str = content.to_string();
+                        quote! {
+                            static FIELDS: ::serde_versions::__private::Fields = ::serde_versions::__private::Fields::new();
+                            ::serde_versions::__private::deserialize_envelope::<#data, _>(#deserializer, check, #content_str, &FIELDS)
+                                #unwrap
+                        }
+                    }
+                    Layout::Keyed { key } => {
+                        let key = key.to_runtime();
+                        quote! {
+                            ::serde_versions::__private::deserialize_keyed::<#data, _>(#deserializer, check, #key)
+                                #unwrap
+                        }
+                    }
+                    Layout::Flat => unreachable!(),
+                }
+            }
+        };
+        // a newer version is kept with the fields it added, to write them back under it
+        let (newer, read) = if forward {
+            (
+                quote! {
+                    let read_newer = std::cell::Cell::new(None);
+                    let newer = Some(&read_newer);
+                },
+                quote! {
+                    let read = { #read };
+                    read.map(|mut value: Self| {
+                        ::serde_versions::__private::keep_newer(&mut value.extra, &read_newer);
+                        value
+                    })
+                },
+            )
+        } else {
+            (quote!(let newer = None;), read)
+        };
+        quote! {
+            const EXPECTED: &::serde_versions::Version = &#expected;
+            #newer
+            let check = ::serde_versions::__private::Check {
+                type_name: stringify!(#name),
+                field: #field_str,
+                repr: #repr,
+                compat: #compat,
+                forward: #forward,
+                newest: #newest,
+                expected: EXPECTED,
+                newer,
+            };
+            #read
+        }
+    };
+
+    // older versions are buffered, dispatched on their version and upgraded, as are fields whose
+    // key, type or presence depends on the version
+    let buffered = args.previous.is_some()
+        || !args.renames.is_empty()
+        || !args.conversions.is_empty()
+        || !args.removed.is_empty()
+        || !args.presence.is_empty()
+        || args.missing.is_some();
+    let (body, bounds) = if buffered {
+        (
+            quote! {
+                <::serde_versions::__private::Migrating<Self> as ::serde_versions::__private::serde::Deserialize>::deserialize(deserializer)
+                    .map(|m| m.0)
+            },
+            quote!(Self: ::serde_versions::__private::Migrate,),
+        )
+    } else {
+        (
+            read_with(quote!(deserializer), None),
+            deserialize_bound(ast, false),
+        )
+    };
+    let at = match &args.layout {
+        Layout::Flat => quote!(Top),
+        Layout::Envelope { content } => {
+            let content_str = content.to_string();
+            quote!(Under(#content_str))
+        }
+        Layout::Keyed { .. } => quote!(Keyed),
+    };
+    // old keys are only read from the versions before their rename
+    let drop_renamed = (!args.renames.is_empty()).then(|| {
+        let renames = args.renames.iter().map(|Rename { from, before }| {
+            quote!(::serde_versions::__private::Rename { from: #from, before: #before })
+        });
+        quote! {
+            ::serde_versions::__private::drop_renamed(
+                &mut content,
+                &version,
+                &[#(#renames),*],
+                ::serde_versions::__private::FieldsAt::#at,
+            );
+        }
+    });
+    // removed fields are read and dropped from the versions before their removal
+    let drop_removed = (!args.removed.is_empty()).then(|| {
+        let removed = args.removed.iter().map(|Removed { name, ty, before }| {
+            let name_str = name.to_string();
+            quote! {
+                ::serde_versions::__private::Removed {
+                    name: #name_str,
+                    before: #before,
+                    read: |value| {
+                        <#ty as ::serde_versions::__private::serde::Deserialize>::deserialize(
+                            ::serde_versions::__private::ContentDeserializer::<E>::new(value),
+                        )
+                        .map(drop)
+                    },
+                }
+            }
+        });
+        quote! {
+            ::serde_versions::__private::drop_removed(
+                &mut content,
+                &version,
+                &[#(#removed),*],
+                ::serde_versions::__private::FieldsAt::#at,
+            )?;
+        }
+    });
+    // fields are only read from the versions that write them
+    let reject_absent = (!args.presence.is_empty()).then(|| {
+        let presence = args.presence.iter().map(|presence| {
+            let name_str = presence.name.to_string();
+            let since = option_tokens(presence.since);
+            let until = option_tokens(presence.until);
+            quote! {
+                ::serde_versions::__private::Presence {
+                    name: #name_str,
+                    since: #since,
+                    until: #until,
+                }
+            }
+        });
+        quote! {
+            ::serde_versions::__private::reject_absent(
+                &mut content,
+                &version,
+                &[#(#presence),*],
+                ::serde_versions::__private::FieldsAt::#at,
+            )?;
+        }
+    });
+    let mut_content = (drop_renamed.is_some() || drop_removed.is_some() || reject_absent.is_some())
+        .then(|| quote!(let mut content = content;));
+    let content_deserializer = quote!(::serde_versions::__private::ContentDeserializer::<E>::new(
+        content
+    ));
+    let befores = befores(args);
+    let read_content = if befores.is_empty() {
+        read_with(content_deserializer, None)
+    } else {
+        let reads: Vec<_> = befores
+            .iter()
+            .map(|before| {
+                let read = read_with(content_deserializer.clone(), Some(*before));
+                quote!(if number < #before { #read } else)
+            })
+            .collect();
+        let read_current = read_with(content_deserializer, None);
+        quote! {
+            let number = match &version {
+                ::serde_versions::Version::Number(number) => *number,
+                _ => u64::MAX,
+            };
+            #(#reads)* { #read_current }
+        }
+    };
+    // formats that can't be buffered read the rest of the payload once its version is known,
+    // straight into the mirror of the version's fields
+    let read_rest = |before: Option<u64>| -> (TokenStream, TokenStream) {
+        if !borrowed.is_empty() {
+            let message = format!(
+                "{} borrows from the input, so it is only read from buffered payloads",
+                name
+            );
+            let unsupported = quote!(Err(<__A::Error as ::serde_versions::__private::serde::de::Error>::custom(#message)));
+            return (unsupported.clone(), unsupported);
+        }
+        let data = match before {
+            Some(before) => {
+                let data = format_ident!("{}Data", before_name(ast, args, before));
+                quote!(#data #remote_turbofish)
+            }
+            None => quote!(Self),
+        };
+        let unwrap = before.map(|_| quote!(.map(|data| data.0)));
+        let not_seq = quote!(Err(
+            <__A::Error as ::serde_versions::__private::serde::de::Error>::custom(format_args!(
+                "expected {} as a map under a version key",
+                stringify!(#name)
+            ))
+        ));
+        let not_entry = quote!(Err(
+            <__A::Error as ::serde_versions::__private::serde::de::Error>::custom(format_args!(
+                "expected {} as a sequence starting with its version",
+                stringify!(#name)
+            ))
+        ));
+        match &args.layout {
+            Layout::Flat => {
+                let mirror = match before {
+                    Some(before) => before_name(ast, args, before),
+                    None => remote_name.clone(),
+                };
+                (
+                    quote!(#mirror #remote_turbofish ::deserialize(::serde_versions::__private::SeqRest::new(seq))),
+                    not_entry,
+                )
+            }
+            Layout::Envelope { .. } => (
+                quote!(::serde_versions::__private::next_data::<#data, _>(seq) #unwrap),
+                not_entry,
+            ),
+            Layout::Keyed { .. } => (
+                not_seq,
+                quote!(::serde_versions::__private::entry_data::<#data, _>(map) #unwrap),
+            ),
+        }
+    };
+    let (read_seq, read_entry) = {
+        let (mut read_seq, mut read_entry) = read_rest(None);
+        for before in befores.iter().rev() {
+            let (seq, entry) = read_rest(Some(*before));
+            read_seq = quote!(if number < #before { #seq } else { #read_seq });
+            read_entry = quote!(if number < #before { #entry } else { #read_entry });
+        }
+        let number = (!befores.is_empty() || !args.removed.is_empty()).then(|| {
+            quote! {
+                let number = match &version {
+                    ::serde_versions::Version::Number(number) => *number,
+                    _ => u64::MAX,
+                };
+            }
+        });
+        // the place of a removed field among the others isn't known
+        let removed = args.removed.iter().map(|removed| removed.before).max().map(|before| {
+            let message = format!(
+                "{} had fields removed by version {}, they are only skipped in buffered payloads",
+                name, before
+            );
+            quote! {
+                if number < #before {
+                    return Err(<__A::Error as ::serde_versions::__private::serde::de::Error>::custom(#message));
+                }
+            }
+        });
+        (
+            quote!(#number #removed #read_seq),
+            quote!(#number #removed #read_entry),
+        )
+    };
+    let own_len = match (&args.layout, &ast.data) {
+        (Layout::Flat, syn::Data::Struct(data)) => data.fields.len() + 1,
+        (Layout::Keyed { .. }, _) => 1,
+        _ => 2,
+    };
+    let stream_len = match &args.previous {
+        Some(previous) => quote! {
+            ::serde_versions::__private::max_len(#own_len, <#previous as ::serde_versions::__private::Migrate>::STREAM.len)
+        },
+        None => quote!(#own_len),
+    };
+    let stream_key = match &args.layout {
+        Layout::Keyed { key } => {
+            let key = key.to_runtime();
+            quote!(Some(#key))
+        }
+        _ => quote!(None),
+    };
+
+    let before_impls = befores
+        .iter()
+        .map(|before| before_impls(ast, args, *before, deserialize));
+    let conversion_impls = conversion_impls(ast, &args.conversions);
+
+    // lets this type read older versions, and lets newer versions name it as `previous`
+    let upgrade_impl = match &args.previous {
+        Some(previous) => {
+            let (upgrade, from_bound) = match &args.upgrade {
+                Some(upgrade) => (quote!(#upgrade(previous)), quote!()),
+                None => (
+                    quote!(<Self as std::convert::From<#previous>>::from(previous)),
+                    quote!(Self: std::convert::From<#previous>,),
+                ),
+            };
+            quote! {
+                impl #impl_generics ::serde_versions::__private::Upgrade for #name #generics
+                where
+                    #(#where_predicates,)*
+                    #from_bound
+                {
+                    type Previous = #previous;
+
+                    fn upgrade(previous: #previous) -> Self {
+                        #upgrade
+                    }
+                }
+            }
+        }
+        None => quote!(),
+    };
+    let from_older_versions = match &args.previous {
+        Some(previous) => quote! {
+            if <#previous as ::serde_versions::__private::Migrate>::accepts(&version) {
+                return <#previous as ::serde_versions::__private::Migrate>::from_content(version, content)
+                    .map(<Self as ::serde_versions::__private::Upgrade>::upgrade);
+            }
+        },
+        None => quote!(),
+    };
+    let from_older_seq = args.previous.as_ref().map(|previous| {
+        quote! {
+            if <#previous as ::serde_versions::__private::Migrate>::accepts(&version) {
+                return <#previous as ::serde_versions::__private::Migrate>::from_seq(version, seq)
+                    .map(<Self as ::serde_versions::__private::Upgrade>::upgrade);
+            }
+        }
+    });
+    let from_older_entry = args.previous.as_ref().map(|previous| {
+        quote! {
+            if <#previous as ::serde_versions::__private::Migrate>::accepts(&version) {
+                return <#previous as ::serde_versions::__private::Migrate>::from_entry(version, map)
+                    .map(<Self as ::serde_versions::__private::Upgrade>::upgrade);
+            }
+        }
+    });
+    let mismatch = quote! {
+        Err(::serde_versions::VersionError::Mismatch {
+            type_name: stringify!(#name),
+            expected: <Self as ::serde_versions::__private::Migrate>::VERSION.clone(),
+            found: version,
+        }
+        .into_de_error())
+    };
+    // older versions may have stored their version under another field
+    let version_of_previous = match &args.previous {
+        Some(previous) => quote! {
+            .or_else(|err| <#previous as ::serde_versions::__private::Migrate>::version_of(content).map_err(|_: E| err))
+        },
+        None => quote!(),
+    };
+    let version_of = match &args.layout {
+        Layout::Keyed { key } => {
+            let key = key.to_runtime();
+            quote!(::serde_versions::__private::keyed_version(content, #key, #repr))
+        }
+        _ => quote!(::serde_versions::__private::content_version(content, #field_str, #repr)),
+    };
+    let peek = match &args.layout {
+        Layout::Keyed { key } => {
+            let key = key.to_runtime();
+            quote!(::serde_versions::__private::peek_keyed(deserializer, stringify!(#name), #key, #repr))
+        }
+        _ => {
+            quote!(::serde_versions::__private::peek_field(deserializer, stringify!(#name), &[#field_str], #repr))
+        }
+    };
+    // payloads without a version get the one assumed by `missing`, here or on a predecessor
+    let assume_version = match (&args.missing, &args.previous) {
+        (Some(missing), _) => {
+            let missing = missing.to_version();
+            let legacy = match &args.layout {
+                Layout::Flat => quote!(Field(#field_str)),
+                Layout::Envelope { content } => {
+                    let content_str = content.to_string();
+                    quote!(Envelope { field: #field_str, content: #content_str })
+                }
+                Layout::Keyed { key } => {
+                    let key = key.to_runtime();
+                    quote!(Keyed(#key, #repr))
+                }
+            };
+            quote! {
+                const MISSING: &::serde_versions::Version = &#missing;
+                ::serde_versions::__private::assume_version(
+                    content,
+                    MISSING,
+                    ::serde_versions::__private::Legacy::#legacy,
+                )
+            }
+        }
+        (None, Some(previous)) => {
+            quote!(<#previous as ::serde_versions::__private::Migrate>::assume_version(content))
+        }
+        (None, None) => quote!(Err(content)),
+    };
+    // a type describing its own history leaves the versions its predecessors know to them
+    let leave_to_previous = match (&args.compat, &args.previous) {
+        (Compat::Older, Some(previous)) => {
+            quote!(&& !<#previous as ::serde_versions::__private::Migrate>::accepts(version))
+        }
+        _ => quote!(),
+    };
+    let reads_newer = args.forward.as_ref().map(|_| {
+        let newest = option_tokens(args.newest);
+        quote!(|| ::serde_versions::__private::reads_newer(
+            <Self as ::serde_versions::__private::Migrate>::VERSION,
+            version,
+            #newest,
+        ))
+    });
+    let accepts_previous = match &args.previous {
+        Some(previous) => {
+            quote!(|| <#previous as ::serde_versions::__private::Migrate>::accepts(version))
+        }
+        None => quote!(),
+    };
+
+    quote! {
+        #remote
+        #(#before_impls)*
+        #conversion_impls
+
+        impl #de_impl_generics ::serde_versions::__private::serde::Deserialize<'de> for #name #generics
+        where
+            #(#where_predicates,)*
+            #bounds
+            #('de: #borrowed,)*
+        {
+            fn deserialize<__D>(deserializer: __D) -> Result<Self, __D::Error>
+            where
+                __D: ::serde_versions::__private::serde::Deserializer<'de>,
+            {
+                #body
+            }
+        }
+
+        impl #impl_generics ::serde_versions::__private::Migrate for #name #generics
+        where
+            #(#where_predicates,)*
+            #owned_bound
+        {
+            const VERSION: &'static ::serde_versions::Version = &#expected;
+
+            fn reads(version: &::serde_versions::Version) -> bool {
+                #compat.accepts(<Self as ::serde_versions::__private::Migrate>::VERSION, version) #leave_to_previous #reads_newer
+            }
+
+            fn accepts(version: &::serde_versions::Version) -> bool {
+                Self::reads(version) #accepts_previous
+            }
+
+            fn version_of<E: ::serde_versions::__private::serde::de::Error>(
+                content: &::serde_versions::__private::Content,
+            ) -> Result<::serde_versions::Version, E> {
+                #version_of #version_of_previous
+            }
+
+            fn assume_version(
+                content: ::serde_versions::__private::Content,
+            ) -> Result<(::serde_versions::Version, ::serde_versions::__private::Content), ::serde_versions::__private::Content> {
+                #assume_version
+            }
+
+            fn from_content<E: ::serde_versions::__private::serde::de::Error>(
+                version: ::serde_versions::Version,
+                content: ::serde_versions::__private::Content,
+            ) -> Result<Self, E> {
+                if Self::reads(&version) {
+                    #mut_content
+                    #drop_renamed
+                    #drop_removed
+                    #reject_absent
+                    #read_content
+                } else {
+                    #from_older_versions
+                    #mismatch
+                }
+            }
+
+            const STREAM: ::serde_versions::__private::Stream = ::serde_versions::__private::Stream {
+                type_name: stringify!(#name),
+                repr: #repr,
+                key: #stream_key,
+                len: #stream_len,
+            };
+
+            fn from_seq<'de, __A: ::serde_versions::__private::serde::de::SeqAccess<'de>>(
+                version: ::serde_versions::Version,
+                seq: __A,
+            ) -> Result<Self, __A::Error> {
+                if Self::reads(&version) {
+                    #read_seq
+                } else {
+                    #from_older_seq
+                    #mismatch
+                }
+            }
+
+            fn from_entry<'de, __A: ::serde_versions::__private::serde::de::MapAccess<'de>>(
+                version: ::serde_versions::Version,
+                map: &mut __A,
+            ) -> Result<Self, __A::Error> {
+                if Self::reads(&version) {
+                    #read_entry
+                } else {
+                    #from_older_entry
+                    #mismatch
+                }
+            }
+        }
+
+        impl #impl_generics ::serde_versions::__private::Peek for #name #generics #where_clause {
+            fn peek<'de, __D>(deserializer: __D) -> Result<::serde_versions::Version, __D::Error>
+            where
+                __D: ::serde_versions::__private::serde::Deserializer<'de>,
+            {
+                #peek
+            }
+        }
+
+        #upgrade_impl
+    }
+}
+
+/// Name of the `#[serde(remote)]` mirror of the original type
+pub(crate) fn remote_name(ast: &DeriveInput, args: &VersionArgs) -> Ident {
+    format_ident!("_{}v{}Remote", ast.ident, args.version.ident_suffix())
+}
+
+/// A copy of the original type, keeping only serde attributes, whose derives read and write the
+/// original type with its own layout
+pub(crate) fn remote(ast: &DeriveInput, args: &VersionArgs, derives: &[&Path]) -> DeriveInput {
+    let mut remote = ast.clone();
+    remote.ident = remote_name(ast, args);
+    retain_serde(&mut remote.attrs);
+    default_from(&mut remote.attrs, "::std::default::Default::default");
+    let remote_str = ast.ident.to_string();
+    remote
+        .attrs
+        .insert(0, syn::parse_quote!(#[serde(remote = #remote_str)]));
+    remote
+        .attrs
+        .insert(0, syn::parse_quote!(#[derive(#(#derives),*)]));
+    remote
+        .attrs
+        .insert(0, syn::parse_quote!(#[allow(dead_code)]));
+    let retain_field_attrs = |fields: &mut syn::Fields| {
+        for field in fields.iter_mut() {
+            retain_serde(&mut field.attrs);
+        }
+    };
+    match &mut remote.data {
+        syn::Data::Struct(data) => retain_field_attrs(&mut data.fields),
+        syn::Data::Enum(data) => {
+            for variant in data.variants.iter_mut() {
+                retain_serde(&mut variant.attrs);
+                retain_field_attrs(&mut variant.fields);
+            }
+        }
+        syn::Data::Union(_) => {}
+    }
+    remote
+}
+
+/// Where-predicates for reading `ast`: the container's `#[serde(bound(deserialize = "..."))]` when
+/// given, as the mirrors keep it, otherwise every type parameter being `Deserialize<'de>`. With
+/// `owned` there is no `'de` in scope, so they have to hold for any `'de`.
+pub(crate) fn deserialize_bound(ast: &DeriveInput, owned: bool) -> TokenStream {
+    let de: Lifetime = syn::parse_quote!('de);
+    match serde_bound(&ast.attrs, "deserialize") {
+        Some(mut bound) if owned => {
+            for predicate in bound.iter_mut() {
+                if let WherePredicate::Type(predicate) = predicate {
+                    predicate
+                        .lifetimes
+                        .get_or_insert_with(|| syn::parse_quote!(for<>))
+                        .lifetimes
+                        .push(syn::LifetimeDef::new(de.clone()));
+                }
+            }
+            quote!(#(#bound,)*)
+        }
+        Some(bound) => quote!(#(#bound,)*),
+        None => {
+            let type_params = ast.generics.type_params().map(|param| &param.ident);
+            if owned {
+                quote!(#(#type_params: ::serde_versions::__private::serde::de::DeserializeOwned,)*)
+            } else {
+                quote!(#(#type_params: ::serde_versions::__private::serde::Deserialize<#de>,)*)
+            }
+        }
+    }
+}
+
+/// The versions older payloads are told apart at: those older than a type change are read with
+/// the old types of the fields, then converted, and those older than a field's `since` or `until`
+/// without or with it
+pub(crate) fn befores(args: &VersionArgs) -> Vec<u64> {
+    let mut befores: Vec<u64> = args.conversions.iter().map(|c| c.before).collect();
+    befores.extend(
+        args.presence
+            .iter()
+            .flat_map(|presence| presence.since.into_iter().chain(presence.until))
+            .filter(|before| *before > 0),
+    );
+    befores.sort_unstable();
+    befores.dedup();
+    befores
+}
+
+/// Name of the mirror reading payloads older than `before`
+pub(crate) fn before_name(ast: &DeriveInput, args: &VersionArgs, before: u64) -> Ident {
+    format_ident!(
+        "_{}v{}Before{}",
+        ast.ident,
+        args.version.ident_suffix(),
+        before
+    )
+}
+
+/// `Some(value)` or `None` as tokens
+fn option_tokens(value: Option<u64>) -> TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
+
+/// Name of the function reading a field as its old type
+fn convert_name(conversion: &Conversion) -> Ident {
+    format_ident!("__{}_before_{}", conversion.field, conversion.before)
+}
+
+/// A `#[serde(remote)]` mirror reading the fields changed at or after `before` as their old types,
+/// and only the fields the versions just before it write, along with a `DeserializeData` wrapper
+/// around it for the layouts and frames that need one
+fn before_impls(
+    ast: &DeriveInput,
+    args: &VersionArgs,
+    before: u64,
+    deserialize: &Path,
+) -> TokenStream {
+    let name = &ast.ident;
+    let mut mirror = remote(ast, args, &[deserialize]);
+    mirror.ident = before_name(ast, args, before);
+    if let syn::Data::Struct(data) = &mut mirror.data {
+        for field in data.fields.iter_mut() {
+            let conversion = args
+                .conversions
+                .iter()
+                .find(|c| field.ident.as_ref() == Some(&c.field) && c.before >= before);
+            if let Some(conversion) = conversion {
+                let convert_name = convert_name(conversion);
+                let (_, generics, _) = ast.generics.split_for_impl();
+                let path = quote!(<#name #generics>::#convert_name).to_string();
+                field
+                    .attrs
+                    .push(syn::parse_quote!(#[serde(deserialize_with = #path)]));
+            }
+            let presence = args
+                .presence
+                .iter()
+                .find(|presence| field.ident.as_ref() == Some(&presence.name));
+            if let Some(presence) = presence {
+                shape_field(field, presence, before.saturating_sub(1));
+            }
+        }
+    }
+    if !args.data_impls() {
+        return quote!(#mirror);
+    }
+
+    let mirror_name = &mirror.ident;
+    let data = format_ident!("{}Data", mirror_name);
+    let turbofish = turbofish(&ast.generics);
+    let (_, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+    let bound = deserialize_bound(ast, false);
+    let mut de_generics = ast.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    let decl_generics = &ast.generics;
+    quote! {
+        #mirror
+
+        struct #data #decl_generics (#name #generics) #where_clause;
+
+        impl #de_impl_generics ::serde_versions::__private::DeserializeData<'de> for #data #generics
+        where
+            #(#where_predicates,)*
+            #bound
+        {
+            fn deserialize_data<__D>(deserializer: __D) -> Result<Self, __D::Error>
+            where
+                __D: ::serde_versions::__private::serde::Deserializer<'de>,
+            {
+                #mirror_name #turbofish ::deserialize(deserializer).map(#data)
+            }
+        }
+    }
+}
+
+/// Functions reading a field as its old type and converting it, used by the mirrors of older
+/// versions through `#[serde(deserialize_with)]`
+fn conversion_impls(ast: &DeriveInput, conversions: &[Conversion]) -> TokenStream {
+    if conversions.is_empty() {
+        return quote!();
+    }
+    let name = &ast.ident;
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let functions = conversions.iter().map(|conversion| {
+        let Conversion { ty, old, .. } = conversion;
+        let convert_name = convert_name(conversion);
+        let convert = match &conversion.convert {
+            Some(convert) => quote!(#convert),
+            None => quote!(<#ty as std::convert::From<#old>>::from),
+        };
+        quote! {
+            #[doc(hidden)]
+            fn #convert_name<'de, __D>(deserializer: __D) -> Result<#ty, __D::Error>
+            where
+                __D: ::serde_versions::__private::serde::Deserializer<'de>,
+                #old: ::serde_versions::__private::serde::Deserialize<'de>,
+            {
+                <#old as ::serde_versions::__private::serde::Deserialize<'de>>::deserialize(deserializer)
+                    .map(#convert)
+            }
+        }
+    });
+    quote! {
+        impl #impl_generics #name #generics #where_clause {
+            #(#functions)*
+        }
+    }
+}
+
+/// Lifetimes the deserialized value may borrow from the input, following serde's rules:
+/// those of `&'a` references and of fields marked `#[serde(borrow)]`.
+pub(crate) fn borrowed_lifetimes(ast: &DeriveInput) -> Vec<Lifetime> {
+    let fields: Vec<&syn::Field> = match &ast.data {
+        syn::Data::Struct(data) => data.fields.iter().collect(),
+        syn::Data::Enum(data) => data.variants.iter().flat_map(|v| v.fields.iter()).collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+    ast.generics
+        .lifetimes()
+        .map(|def| &def.lifetime)
+        .filter(|lifetime| {
+            fields.iter().any(|field| {
+                let borrow = field.attrs.iter().any(|attr| {
+                    attr.path.is_ident("serde") && mentions_borrow(attr.tokens.clone())
+                });
+                let ty = &field.ty;
+                uses_lifetime(quote!(#ty), lifetime, borrow)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether `tokens` contain `&'lifetime`, or any mention of `'lifetime` when `anywhere` is set
+fn uses_lifetime(tokens: TokenStream, lifetime: &Lifetime, anywhere: bool) -> bool {
+    let mut after_ref = false;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Group(group) => {
+                if uses_lifetime(group.stream(), lifetime, anywhere) {
+                    return true;
+                }
+                after_ref = false;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '&' => after_ref = true,
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                if let Some(TokenTree::Ident(ident)) = tokens.peek() {
+                    if *ident == lifetime.ident && (anywhere || after_ref) {
+                        return true;
+                    }
+                }
+                after_ref = false;
+            }
+            _ => after_ref = false,
+        }
+    }
+    false
+}
+
+fn mentions_borrow(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Group(group) => mentions_borrow(group.stream()),
+        TokenTree::Ident(ident) => ident == "borrow",
+        _ => false,
+    })
+}
@@ -0,0 +1,78 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::args::VersionArgs;
+
+/// Generate the `Downgrade` impl writing the type as its own version, or as an older one by
+/// converting to `previous` with `TryFrom<Self>`.
+///
+/// Types with `previous` only get it with `downgrade`, as it needs the conversion.
+pub(crate) fn downgrade_impl(ast: &DeriveInput, args: &VersionArgs) -> TokenStream {
+    let name = &ast.ident;
+    let expected = args.version.to_version();
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+    let (to_previous, previous_bounds) = match (&args.previous, &args.downgrade) {
+        (None, _) => (
+            quote!(::serde_versions::__private::serde::Serialize::serialize(
+                &self, serializer
+            )),
+            quote!(),
+        ),
+        (Some(previous), Some(_)) => (
+            quote! {
+                if version == #expected {
+                    return ::serde_versions::__private::serde::Serialize::serialize(&self, serializer);
+                }
+                let previous = <#previous as std::convert::TryFrom<Self>>::try_from(self).map_err(|err| {
+                    <__S::Error as ::serde_versions::__private::serde::ser::Error>::custom(format_args!(
+                        "{} can't be written as version {}: {}",
+                        stringify!(#name),
+                        version,
+                        err
+                    ))
+                })?;
+                ::serde_versions::Downgrade::serialize_as_version(previous, version, serializer)
+            },
+            quote! {
+                #previous: std::convert::TryFrom<Self> + ::serde_versions::Downgrade,
+                <#previous as std::convert::TryFrom<Self>>::Error: std::fmt::Display,
+            },
+        ),
+        (Some(_), None) => return quote!(),
+    };
+    let writes_previous = args
+        .previous
+        .as_ref()
+        .map(|previous| quote!(|| <#previous as ::serde_versions::Downgrade>::writes(version)));
+
+    quote! {
+        impl #impl_generics ::serde_versions::Downgrade for #name #generics
+        where
+            #(#where_predicates,)*
+            Self: ::serde_versions::__private::serde::Serialize,
+            #previous_bounds
+        {
+            fn serialize_as_version<__S: ::serde_versions::__private::serde::Serializer>(
+                self,
+                version: impl ::serde_versions::IntoVersion,
+                serializer: __S,
+            ) -> Result<__S::Ok, __S::Error> {
+                let version = ::serde_versions::IntoVersion::into_version(version);
+                if !<Self as ::serde_versions::Downgrade>::writes(&version) {
+                    return Err(<__S::Error as ::serde_versions::__private::serde::ser::Error>::custom(
+                        format_args!("{} has no version {} to write", stringify!(#name), version),
+                    ));
+                }
+                #to_previous
+            }
+
+            fn writes(version: &::serde_versions::Version) -> bool {
+                *version == #expected #writes_previous
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Path};
+
+use crate::args::VersionArgs;
+use crate::attrs::serde_bound;
+use crate::de::{borrowed_lifetimes, deserialize_bound, remote, remote_name};
+use crate::ser::turbofish;
+
+/// Generate the `SerializeData` and `DeserializeData` impls the envelope layout uses to write the
+/// value with its own layout, through a `#[serde(remote)]` mirror of it.
+///
+/// `ast` is the original type, still carrying its serde attributes.
+pub(crate) fn data_impls(
+    ast: &DeriveInput,
+    args: &VersionArgs,
+    serialize: Option<&Path>,
+    deserialize: Option<&Path>,
+) -> TokenStream {
+    let name = &ast.ident;
+    let derives: Vec<_> = serialize.into_iter().chain(deserialize).collect();
+    if derives.is_empty() {
+        return quote!();
+    }
+    let remote = remote(ast, args, &derives);
+    let remote_name = remote_name(ast, args);
+    let remote_turbofish = turbofish(&ast.generics);
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+    let type_params: Vec<_> = ast
+        .generics
+        .type_params()
+        .map(|param| &param.ident)
+        .collect();
+
+    let serialize_data = serialize.map(|_| {
+        let bound = match serde_bound(&ast.attrs, "serialize") {
+            Some(bound) => quote!(#(#bound,)*),
+            None => quote!(#(#type_params: ::serde_versions::__private::serde::Serialize,)*),
+        };
+        quote! {
+            impl #impl_generics ::serde_versions::__private::SerializeData for #name #generics
+            where
+                #(#where_predicates,)*
+                #bound
+            {
+                fn serialize_data<__S>(&self, serializer: __S) -> Result<__S::Ok, __S::Error>
+                where
+                    __S: ::serde_versions::__private::serde::Serializer,
+                {
+                    #remote_name #remote_turbofish ::serialize(self, serializer)
+                }
+            }
+        }
+    });
+
+    let deserialize_data = deserialize.map(|_| {
+        let mut de_generics = ast.generics.clone();
+        de_generics.params.insert(0, syn::parse_quote!('de));
+        let (de_impl_generics, _, _) = de_generics.split_for_impl();
+        let borrowed = borrowed_lifetimes(ast);
+        let bound = deserialize_bound(ast, false);
+        quote! {
+            impl #de_impl_generics ::serde_versions::__private::DeserializeData<'de> for #name #generics
+            where
+                #(#where_predicates,)*
+                #bound
+                #('de: #borrowed,)*
+            {
+                fn deserialize_data<__D>(deserializer: __D) -> Result<Self, __D::Error>
+                where
+                    __D: ::serde_versions::__private::serde::Deserializer<'de>,
+                {
+                    #remote_name #remote_turbofish ::deserialize(deserializer)
+                }
+            }
+        }
+    });
+
+    quote! {
+        #remote
+
+        #serialize_data
+
+        #deserialize_data
+    }
+}
@@ -0,0 +1,218 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Path};
+
+use crate::args::{Frame, VersionArgs};
+use crate::de::{before_name, befores};
+use crate::ser::turbofish;
+
+/// Generate `encode_versioned` and `decode_versioned`, which write the version as a raw header in
+/// front of the value encoded with its own layout, and the `Framed` impl dispatching older versions.
+/// The `_with` variants take the `Codec` encoding the value, the others use bincode.
+///
+/// Relies on the `SerializeData` and `DeserializeData` impls of the type. Everything is wrapped in
+/// `framed!`, which reports a missing `bincode` feature of `serde-versions`.
+pub(crate) fn frame_impls(
+    ast: &DeriveInput,
+    args: &VersionArgs,
+    frame: Frame,
+    serialize: Option<&Path>,
+    deserialize: Option<&Path>,
+) -> TokenStream {
+    let name = &ast.ident;
+    let header = frame.to_runtime();
+    let repr = args.repr.to_runtime();
+    let expected = args.version.to_version();
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+
+    let encode = serialize.map(|_| {
+        quote! {
+            /// Write the version as a raw header, followed by the value encoded with bincode
+            pub fn encode_versioned<__W: ::std::io::Write>(
+                &self,
+                writer: __W,
+            ) -> Result<(), ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::SerializeData,
+            {
+                self.encode_versioned_with(&::serde_versions::Bincode, writer)
+            }
+
+            /// Write the version as a raw header, followed by the value encoded with `codec`
+            pub fn encode_versioned_with<__C: ::serde_versions::Codec, __W: ::std::io::Write>(
+                &self,
+                codec: &__C,
+                mut writer: __W,
+            ) -> Result<(), ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::SerializeData,
+            {
+                const VERSION: &::serde_versions::Version = &#expected;
+                #header.write(&mut writer, #repr, VERSION)?;
+                ::serde_versions::__private::encode_data(codec, writer, self)
+            }
+        }
+    });
+
+    let decode = deserialize.map(|_| {
+        quote! {
+            /// Read the version header written by `encode_versioned`, leaving the value in `reader`
+            pub fn read_version<__R: ::std::io::Read>(
+                reader: __R,
+            ) -> Result<::serde_versions::Version, ::serde_versions::FrameError> {
+                #header.read(reader, #repr)
+            }
+
+            /// Decode a value written by `encode_versioned` with the given version, upgrading
+            /// older versions
+            pub fn decode_body<__R: ::std::io::Read>(
+                version: ::serde_versions::Version,
+                reader: __R,
+            ) -> Result<Self, ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::Framed,
+            {
+                Self::decode_body_with(&::serde_versions::Bincode, version, reader)
+            }
+
+            /// Decode a value written by `encode_versioned_with` with the given version, upgrading
+            /// older versions
+            pub fn decode_body_with<__C: ::serde_versions::Codec, __R: ::std::io::Read>(
+                codec: &__C,
+                version: ::serde_versions::Version,
+                reader: __R,
+            ) -> Result<Self, ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::Framed,
+            {
+                <Self as ::serde_versions::__private::Framed>::decode_body(codec, version, reader)
+            }
+
+            /// Decode a value written by `encode_versioned`, upgrading older versions
+            pub fn decode_versioned<__R: ::std::io::Read>(
+                reader: __R,
+            ) -> Result<Self, ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::Framed,
+            {
+                Self::decode_versioned_with(&::serde_versions::Bincode, reader)
+            }
+
+            /// Decode a value written by `encode_versioned_with`, upgrading older versions
+            pub fn decode_versioned_with<__C: ::serde_versions::Codec, __R: ::std::io::Read>(
+                codec: &__C,
+                mut reader: __R,
+            ) -> Result<Self, ::serde_versions::FrameError>
+            where
+                Self: ::serde_versions::__private::Framed,
+            {
+                let version = Self::read_version(&mut reader)?;
+                <Self as ::serde_versions::__private::Framed>::decode_body(codec, version, reader)
+            }
+        }
+    });
+
+    // older versions have to be framed too, their bodies are decoded and upgraded
+    let from_older_versions = args.previous.as_ref().map(|previous| {
+        quote! {
+            if <#previous as ::serde_versions::__private::Migrate>::accepts(&version) {
+                return <#previous as ::serde_versions::__private::Framed>::decode_body(codec, version, reader)
+                    .map(<Self as ::serde_versions::__private::Upgrade>::upgrade);
+            }
+        }
+    });
+    let mismatch = quote! {
+        Err(::serde_versions::VersionError::Mismatch {
+            type_name: stringify!(#name),
+            expected: <Self as ::serde_versions::__private::Migrate>::VERSION.clone(),
+            found: version,
+        }
+        .into())
+    };
+    // bodies written by newer versions have fields this one can't place
+    let reject_newer = args.forward.as_ref().map(|_| {
+        let compat = args.compat.to_runtime();
+        quote! {
+            if !#compat.accepts(<Self as ::serde_versions::__private::Migrate>::VERSION, &version) {
+                return #mismatch;
+            }
+        }
+    });
+    // older versions this type reads itself are decoded with the mirror of the fields they wrote
+    let befores = befores(args);
+    let removed = args.removed.iter().map(|removed| removed.before).max();
+    let number = (!befores.is_empty() || removed.is_some()).then(|| {
+        quote! {
+            let number = match &version {
+                ::serde_versions::Version::Number(number) => *number,
+                _ => u64::MAX,
+            };
+        }
+    });
+    let reject_removed = removed.map(|before| {
+        let message = format!(
+            "{} had fields removed by version {}, they are only skipped in buffered payloads",
+            name, before
+        );
+        quote! {
+            if number < #before {
+                return Err(::serde_versions::FrameError::Body(#message.into()));
+            }
+        }
+    });
+    let turbofish = turbofish(&ast.generics);
+    let older_data: Vec<_> = befores
+        .iter()
+        .map(|before| format_ident!("{}Data", before_name(ast, args, *before)))
+        .collect();
+    let decode_older = befores.iter().zip(&older_data).map(|(before, data)| {
+        quote! {
+            if number < #before {
+                return ::serde_versions::__private::decode_data::<_, _, #data #turbofish>(codec, reader)
+                    .map(|data| data.0);
+            }
+        }
+    });
+    let framed = deserialize.map(|_| {
+        quote! {
+            impl #impl_generics ::serde_versions::__private::Framed for #name #generics
+            where
+                #(#where_predicates,)*
+                Self: ::serde_versions::__private::Migrate
+                    + for<'de> ::serde_versions::__private::DeserializeData<'de>,
+                #(#older_data #generics: for<'de> ::serde_versions::__private::DeserializeData<'de>,)*
+            {
+                fn decode_body<__C: ::serde_versions::Codec, __R: ::std::io::Read>(
+                    codec: &__C,
+                    version: ::serde_versions::Version,
+                    reader: __R,
+                ) -> Result<Self, ::serde_versions::FrameError> {
+                    if <Self as ::serde_versions::__private::Migrate>::reads(&version) {
+                        #reject_newer
+                        #number
+                        #reject_removed
+                        #(#decode_older)*
+                        return ::serde_versions::__private::decode_data(codec, reader);
+                    }
+                    #from_older_versions
+                    #mismatch
+                }
+            }
+        }
+    });
+
+    quote! {
+        ::serde_versions::__private::framed! {
+            impl #impl_generics #name #generics #where_clause {
+                #encode
+
+                #decode
+            }
+
+            #framed
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{Attribute, DeriveInput, Field, Ident, LitInt, LitStr, Token};
+
+use crate::args::{Compat, Conversion, Presence, Rename, VersionArgs, VersionLit};
+
+/// `#[versioned(...)]` on a field, giving the versions the field appears in.
+///
+/// e.g. `#[versioned(since = 2)]`, `#[versioned(until = 4)]`, `#[versioned(since = 3, default = "f")]`
+/// `#[versioned(renamed_from = "old", before = 3)]` or `#[versioned(before = 4, as = "u32", convert = "f")]`
+struct FieldHistory {
+    /// The first version with the field
+    since: Option<(u64, LitInt)>,
+    /// The first version without the field
+    until: Option<(u64, LitInt)>,
+    /// Function filling in the field when it is missing. Defaults to `Default::default`
+    default: Option<LitStr>,
+    /// The key the field was read from before it was renamed
+    renamed_from: Option<LitStr>,
+    /// The first version with the new key or type
+    before: Option<(u64, LitInt)>,
+    /// The type the field had before `before`
+    old_type: Option<LitStr>,
+    /// Function converting the old type. Defaults to `From::from`
+    convert: Option<LitStr>,
+}
+
+impl Parse for FieldHistory {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut history = FieldHistory {
+            since: None,
+            until: None,
+            default: None,
+            renamed_from: None,
+            before: None,
+            old_type: None,
+            convert: None,
+        };
+        while !input.is_empty() {
+            // `as` is a keyword
+            let key = input.call(Ident::parse_any)?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "since" => {
+                    let lit: LitInt = input.parse()?;
+                    history.since = Some((lit.base10_parse()?, lit));
+                }
+                "until" => {
+                    let lit: LitInt = input.parse()?;
+                    history.until = Some((lit.base10_parse()?, lit));
+                }
+                "default" => history.default = Some(input.parse()?),
+                "renamed_from" => history.renamed_from = Some(input.parse()?),
+                "before" => {
+                    let lit: LitInt = input.parse()?;
+                    history.before = Some((lit.base10_parse()?, lit));
+                }
+                "as" => history.old_type = Some(input.parse()?),
+                "convert" => history.convert = Some(input.parse()?),
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("unknown `versioned` argument `{}`", key),
+                    ))
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(history)
+    }
+}
+
+/// Turn the `#[versioned(...)]` attributes of the fields into serde attributes, so a single
+/// `Deserialize` reads every older version, filling in the fields they lack.
+///
+/// Fields the current version doesn't write get `#[serde(skip)]`, and are recorded so the
+/// generated code reads each older version with the fields it wrote. Renamed fields get
+/// `#[serde(alias)]`, and the generated code drops the old key from payloads that are not older
+/// than `before`. Fields whose type changed are recorded so the generated code reads them as their
+/// old type from payloads older than `before`. Once any field has a history the type reads every
+/// version up to its own.
+pub(crate) fn apply(ast: &mut DeriveInput, args: &mut VersionArgs) -> syn::Result<()> {
+    let mut first_history = None;
+    let current = match &args.version {
+        VersionLit::Number(lit) => Some(lit.base10_parse::<u64>()?),
+        _ => None,
+    };
+
+    let fields: Vec<&mut syn::Field> = match &mut ast.data {
+        syn::Data::Struct(data) => data.fields.iter_mut().collect(),
+        syn::Data::Enum(data) => {
+            for field in data.variants.iter().flat_map(|v| v.fields.iter()) {
+                if let Some(attr) = field.attrs.iter().find(|a| a.path.is_ident("versioned")) {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "`#[versioned]` can only be used on fields of structs",
+                    ));
+                }
+            }
+            Vec::new()
+        }
+        syn::Data::Union(_) => Vec::new(),
+    };
+
+    for field in fields {
+        let attr = match field
+            .attrs
+            .iter()
+            .position(|a| a.path.is_ident("versioned"))
+        {
+            Some(index) => field.attrs.remove(index),
+            None => continue,
+        };
+        if field.ident.is_none() {
+            return Err(syn::Error::new_spanned(
+                &attr,
+                "`#[versioned]` can only be used on named fields",
+            ));
+        }
+        let history: FieldHistory = attr.parse_args()?;
+        let current = current.ok_or_else(|| {
+            syn::Error::new_spanned(&attr, "`#[versioned]` needs a numeric version")
+        })?;
+
+        if let Some((since, lit)) = &history.since {
+            if *since > current {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!("`since` can't be newer than version {}", current),
+                ));
+            }
+        }
+        match (&history.since, &history.until) {
+            (Some((since, _)), Some((until, lit))) if until <= since => {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    "`until` has to be after `since`",
+                ))
+            }
+            (_, Some((until, lit))) if *until > current => {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!(
+                        "`until` can't be newer than version {}, the field is still written",
+                        current
+                    ),
+                ))
+            }
+            (None, None) if history.renamed_from.is_none() && history.old_type.is_none() => {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "`#[versioned]` needs `since`, `until`, `renamed_from` or `as`",
+                ))
+            }
+            _ => {}
+        }
+        if let (Some(default), None, None) = (&history.default, &history.since, &history.until) {
+            return Err(syn::Error::new(
+                default.span(),
+                "`default` needs `since` or `until`",
+            ));
+        }
+
+        if let Some((before, lit)) = &history.before {
+            if *before > current {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!("`before` can't be newer than version {}", current),
+                ));
+            }
+            if history.renamed_from.is_none() && history.old_type.is_none() {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    "`before` needs `renamed_from` or `as`",
+                ));
+            }
+        }
+        match (history.renamed_from, &history.before) {
+            (Some(from), Some((before, _))) => {
+                field.attrs.push(syn::parse_quote!(#[serde(alias = #from)]));
+                args.renames.push(Rename {
+                    from,
+                    before: *before,
+                });
+            }
+            (Some(from), None) => {
+                return Err(syn::Error::new(
+                    from.span(),
+                    "`renamed_from` needs `before`, the first version with the new name",
+                ))
+            }
+            (None, _) => {}
+        }
+        match (&history.old_type, &history.before) {
+            (Some(old), Some((before, _))) => args.conversions.push(Conversion {
+                field: field.ident.clone().unwrap(),
+                ty: field.ty.clone(),
+                old: old.parse()?,
+                convert: history.convert.as_ref().map(LitStr::parse).transpose()?,
+                before: *before,
+            }),
+            (Some(old), None) => {
+                return Err(syn::Error::new(
+                    old.span(),
+                    "`as` needs `before`, the first version with the new type",
+                ))
+            }
+            (None, _) => {
+                if let Some(convert) = &history.convert {
+                    return Err(syn::Error::new(convert.span(), "`convert` needs `as`"));
+                }
+            }
+        }
+
+        if history.since.is_some() || history.until.is_some() {
+            let presence = Presence {
+                name: field.ident.clone().unwrap(),
+                since: history.since.map(|(since, _)| since),
+                until: history.until.map(|(until, _)| until),
+                default: history.default,
+            };
+            shape_field(field, &presence, current);
+            args.presence.push(presence);
+        }
+        first_history.get_or_insert_with(|| attr.span());
+    }
+
+    if let Some(span) = first_history {
+        match args.compat {
+            Compat::Exact | Compat::Older => args.compat = Compat::Older,
+            Compat::Major | Compat::Minor => {
+                return Err(syn::Error::new(
+                    span,
+                    "`#[versioned]` reads every older version and can't be combined with `compat`",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Give `field` the shape it has in payloads of `version`: fields that version doesn't write are
+/// skipped and filled in, the others are read as usual
+pub(crate) fn shape_field(field: &mut Field, presence: &Presence, version: u64) {
+    let skip: Attribute = match &presence.default {
+        Some(default) => syn::parse_quote!(#[serde(skip, default = #default)]),
+        None => syn::parse_quote!(#[serde(skip, default)]),
+    };
+    let skip_str = skip.to_token_stream().to_string();
+    field
+        .attrs
+        .retain(|attr| attr.to_token_stream().to_string() != skip_str);
+    if !presence.written_in(version) {
+        field.attrs.push(skip);
+    }
+}
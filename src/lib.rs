@@ -1,20 +1,28 @@
 //! # Serde Versions Derive
 //!
-//!  `serde_versions_derive` exports an attribute macro that adds versioning support for structs.
+//!  `serde_versions_derive` exports an attribute macro that adds versioning support for structs and enums.
 //!  
 //!  When serializing a named field struct it will automatically add a new field containing the version.
+//!  Tuple structs get the version as their first element and unit structs serialize as `{"version": N}`.
+//!  Enums are wrapped in a struct holding the version next to the flattened enum, so the version
+//!  sits in the same object as an internal or adjacent tag, or next to the variant key for external tagging.
 //!  It also allows deserializing the versioned type directly back to the unversioned one.
 //!  
-//!  Under the hood this works by creating a new struct that wraps the original struct plus adds a version byte field.
-//!  Internally this new struct uses `#[serde(flatten)]` to serialize as expected.
-//!  The original struct uses `#[serde(to, from)]` to add the version field when serializing and remove it when deserializing.
+//!  Under the hood this works by creating a new struct that copies the original struct plus adds a version byte field.
+//!  Serializing is done through a borrowing copy of that struct, so the value is never cloned.
+//!  Deserializing reads the fields straight into the original struct through a `#[serde(remote)]` copy of it,
+//!  checking the version as soon as it is read, without building the versioned struct first.
+//!  Deserializing fails if the version in the payload does not match the version of the struct.
+//!
+//!  The generated code refers to types in the companion `serde-versions` crate, which must also be
+//!  a dependency. It re-exports this macro as `serde_versions::version`.
 //!
 //! usage:
 //! ```no_run
 //! # use serde::{Deserialize, Serialize};
 //! # use serde_versions_derive::version;
 //! #[version(3)]
-//! #[derive(Clone, Serialize, Deserialize)]
+//! #[derive(Serialize, Deserialize)]
 //! struct S {
 //!     i: i32,
 //! }
@@ -22,61 +30,709 @@
 //!
 //! This produces the following
 //! ```ignore
-//! #[derive(Clone, Serialize, Deserialize)]
-//! #[serde(into = "_Sv3", from = "_Sv3")]
 //! struct S {
 //!     i: i32,
 //! }
 //!
-//! #[derive(Clone, Serialize, Deserialize)]
+//! #[derive(Serialize, Deserialize)]
 //! struct _Sv3 {
 //!     version: u8,
-//!     #[serde(flatten)]
-//!     inner: S
+//!     i: i32,
 //! }
 //!
-//! // plus implementations of To, From and to_versioned() for S
-//! ```
+//! #[derive(Serialize)]
+//! struct _Sv3Ref<'a> {
+//!     version: u8,
+//!     i: &'a i32,
+//! }
 //!
-//! This supports types with type parameters however these must have a trait bound
-//! to implement Clone
+//! #[derive(Deserialize)]
+//! #[serde(remote = "S")]
+//! struct _Sv3Remote {
+//!     i: i32,
+//! }
 //! 
+//! // plus implementations of Serialize, Deserialize, From, TryFrom, Versioned, Downgrade and into_versioned() for S
+//! ```
+//!
+//! This supports types with type parameters, lifetimes and const generics.
+//!
 //! e.g.:
 //! ```no_run
 //! # use serde::{Deserialize, Serialize};
 //! # use serde_versions_derive::version;
 //! #[version(3)]
-//! #[derive(Clone, Serialize, Deserialize)]
-//! struct S<T: Clone> {
+//! #[derive(Serialize, Deserialize)]
+//! struct S<T> {
 //!     t: T,
 //! }
 //! ```
+//!
+//! ## Generic code
+//!
+//! Every versioned type implements `serde_versions::Versioned`, with its `VERSION`, the generated
+//! `Versioned` struct, `into_versioned()` and `try_from_versioned()`, so code can be generic over
+//! versioned types.
+//! ```ignore
+//! fn save<T: Versioned + Serialize>(value: &T) {
+//!     log::info!("saving {} v{}", std::any::type_name::<T>(), T::VERSION);
+//! }
+//! ```
+//!
+//! ## Field name
+//!
+//! The version is stored in a field called `version` by default. `field` chooses another name,
+//! used both as the serialized key and as the field of the generated structs.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3, field = "schema_version")]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//! ```
+//!
+//! ## Version type
+//!
+//! Versions are written as a `u8` by default. `repr` picks a wider integer type, and a string literal
+//! gives a string version. Version literals that don't fit the chosen type are rejected at compile time.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(70000, repr = u32)]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//!
+//! #[version("2024-01")]
+//! #[derive(Serialize, Deserialize)]
+//! struct T {
+//!     i: i32,
+//! }
+//! ```
+//!
+//! Strings of the form `MAJOR.MINOR.PATCH` are semantic versions. They read any payload with the same
+//! major version and a minor version no higher than their own, so minor bumps stay readable during
+//! rolling deploys. `compat = "major"` ignores the minor version and `compat = "exact"` only reads
+//! the exact version.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version("1.4.0")]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//! ```
+//!
+//! ## Envelope
+//!
+//! Instead of adding the version to the value's own fields, `repr = "envelope"` wraps the value:
+//! `{"version": 3, "data": {...}}`. The value keeps its own serde layout under `content` (`data` by
+//! default), so tuple structs, newtypes, `#[serde(transparent)]` types and enums can be versioned too.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3, repr = "envelope", content = "payload")]
+//! #[derive(Serialize, Deserialize)]
+//! struct Ids(Vec<u64>);
+//! ```
+//!
+//! ## Keyed
+//!
+//! `repr = "keyed"` writes the value, with its own serde layout, as the only entry of a map keyed by
+//! its version: `{"v3": {...}}`. The version is known before the value is parsed. `key` sets the
+//! format of the key, `{}` standing for the version (`"v{}"` by default).
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3, repr = "keyed", key = "schema-{}")]
+//! #[derive(Serialize, Deserialize)]
+//! struct Point(i32, i32);
+//! ```
+//!
+//! ## Framing
+//!
+//! `frame` adds `encode_versioned` and `decode_versioned`, which write the version as a raw header
+//! in front of the value encoded with bincode, instead of inside the serde data model. The header is
+//! either a LEB128 varint (`frame = "varint"`) or a little endian number as wide as `repr`
+//! (`frame = "fixed"`); string versions are prefixed with their length. `read_version` reads just the
+//! header, so the decoder can be chosen from it, and `decode_body` reads the rest. `decode_versioned`
+//! upgrades older versions, which have to be framed too, and reads the older versions described by
+//! field histories. The newer versions `forward` reads are rejected, as the body doesn't say which
+//! fields they added. This needs the `bincode` feature of `serde-versions`. To encode the value
+//! with another format, such as postcard, implement `serde_versions::Codec` and pass it to
+//! `encode_versioned_with`, `decode_versioned_with` or `decode_body_with`.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3, frame = "varint")]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//!
+//! # fn main() -> Result<(), serde_versions::FrameError> {
+//! let mut bytes = Vec::new();
+//! S { i: 1 }.encode_versioned(&mut bytes)?;
+//! let s = S::decode_versioned(&bytes[..])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Migrations
+//!
+//! `previous` names the type describing the prior version. Deserializing reads the version first,
+//! deserializes the payload as the matching historical type and upgrades it step by step using
+//! `From<Previous>`, or the function given by `upgrade`.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(1)]
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct SV1 {
+//!     i: i32,
+//! }
+//!
+//! #[version(2, previous = SV1)]
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//!     j: i32,
+//! }
+//!
+//! impl From<SV1> for S {
+//!     fn from(s: SV1) -> S {
+//!         S { i: s.i, j: 0 }
+//!     }
+//! }
+//! ```
+//!
+//! ## Downgrading
+//!
+//! To write data services not yet upgraded can read, e.g. when rolling back a deploy, the
+//! `serde_versions::Downgrade` trait serializes a value as an older version with
+//! `s.serialize_as_version(2, serializer)`. With `downgrade`, a type converts itself into `previous`
+//! with `TryFrom<Self>`, step by step. The conversion should fail rather than lose information,
+//! which makes the serialization fail. Every type in the chain needs `downgrade`, except the oldest.
+//! `s.to_version::<SV2>()` does a single conversion.
+//! ```ignore
+//! #[version(3, previous = SV2, downgrade)]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//!     j: Option<i32>,
+//! }
+//!
+//! impl TryFrom<S> for SV2 {
+//!     type Error = &'static str;
+//!
+//!     fn try_from(s: S) -> Result<SV2, Self::Error> {
+//!         match s.j {
+//!             None => Ok(SV2 { i: s.i }),
+//!             Some(_) => Err("`j` is not known to version 2"),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! ## Legacy payloads
+//!
+//! Data written before adopting `#[version]` has no version. `missing` gives the version such payloads
+//! are read as, e.g. `missing = 0` or `missing = "legacy"`. They go through `previous` like any other
+//! older version, so a type describing the legacy data can upgrade it. A legacy payload is the bare
+//! value, given the assumed version the way the type with `missing` writes it.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(0)]
+//! #[derive(Serialize, Deserialize)]
+//! struct Unversioned {
+//!     i: i32,
+//! }
+//!
+//! #[version(1, missing = 0, previous = Unversioned)]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i64,
+//! }
+//!
+//! impl From<Unversioned> for S {
+//!     fn from(s: Unversioned) -> S {
+//!         S { i: s.i.into() }
+//!     }
+//! }
+//! ```
+//!
+//! ## Field history
+//!
+//! Instead of keeping a copy of every older struct, fields can describe their own history with
+//! `#[versioned(...)]`. `since` is the first version with the field and `until` the first version
+//! without it. The type then reads every version up to its own, each with the fields it writes. Fields
+//! a version doesn't write are filled in with `Default::default`, or the function given by `default`,
+//! and payloads of that version carrying them are rejected. Fields removed by the current version
+//! are only read from older payloads and no longer written. Versions known to `previous` are still
+//! read through it.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! # fn unnamed() -> String { String::new() }
+//! #[version(4)]
+//! #[derive(Serialize, Deserialize)]
+//! struct Account {
+//!     id: u32,
+//!     #[versioned(since = 2)]
+//!     email: Option<String>,
+//!     #[versioned(since = 3, default = "unnamed")]
+//!     name: String,
+//!     #[versioned(until = 4)]
+//!     legacy_flags: u8,
+//! }
+//! ```
+//!
+//! `renamed_from` gives the key a field was read from before it was renamed, and `before` the first
+//! version using the new key. Older payloads are read from the old key, newer ones only from the new
+//! one. The payload is buffered to read its version first, so these types can't borrow from it.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3)]
+//! #[derive(Serialize, Deserialize)]
+//! struct User {
+//!     #[versioned(renamed_from = "name", before = 3)]
+//!     full_name: String,
+//! }
+//! ```
+//!
+//! When a field's type changes, `as` gives the type it had before version `before`. Older payloads
+//! read the field as that type and convert it with the function given by `convert`, or `From::from`.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! # use std::time::Duration;
+//! fn secs_to_duration(secs: u32) -> Duration {
+//!     Duration::from_secs(secs.into())
+//! }
+//!
+//! #[version(4)]
+//! #[derive(Serialize, Deserialize)]
+//! struct Config {
+//!     #[versioned(before = 4, as = "u32", convert = "secs_to_duration")]
+//!     timeout: Duration,
+//! }
+//! ```
+//!
+//! Fields deleted from the type are listed with `removed(...)` and the first version without them.
+//! Payloads older than that may still carry them, even under `deny_unknown_fields`. Their value is
+//! checked against the given type and dropped. Newer payloads carrying them are rejected.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(5, removed(legacy_flag: bool, before = 5))]
+//! #[derive(Serialize, Deserialize)]
+//! #[serde(deny_unknown_fields)]
+//! struct Settings {
+//!     theme: String,
+//! }
+//! ```
+//!
+//! ## Forward compatibility
+//!
+//! With `forward`, older code reads payloads from newer versions too: semantic versions with the same
+//! major version, or numbers up to the one given, as in `forward = 5`. Those versions should only
+//! add fields. The fields it doesn't know are kept in an added
+//! `extra: serde_versions::Extra` field, flattened, and written back out when serializing so
+//! documents pass through without losing data. They are written under the newer version they were
+//! read from, so newer code reads them back. Values built by hand set it to `Default::default()`.
+//! Flattening needs a self-describing format such as JSON.
+//! ```no_run
+//! # use serde::{Deserialize, Serialize};
+//! # use serde_versions_derive::version;
+//! #[version(3, forward = 5)]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//!
+//! let s = S {
+//!     i: 1,
+//!     extra: Default::default(),
+//! };
+//! ```
+//!
+//! ## All versions
+//!
+//! `versions(...)` lists every older version. It generates an enum of all known versions,
+//! `<Name>Versions`, which deserializes by dispatching on the version field and can be upgraded
+//! with `into_latest()`. The newest listed version is used as `previous` unless one is given.
+//! ```ignore
+//! #[version(3, versions(1 = SV1, 2 = SV2))]
+//! #[derive(Clone, Serialize, Deserialize)]
+//! struct S {
+//!     i: i32,
+//! }
+//!
+//! // generates
+//! enum SVersions {
+//!     V1(SV1),
+//!     V2(SV2),
+//!     V3(S),
+//! }
+//! ```
+//!
+//! ## Binary formats
+//!
+//! Reading older versions normally buffers the payload to find its version first, which needs a
+//! self-describing format. In formats that aren't human readable, such as bincode, the version is
+//! read as the first element instead and the rest is read straight into the type that writes it, so
+//! `previous` chains and `<Name>Versions` work there too. Every version in the chain has to write its
+//! version with the same `repr` and, for `repr = "keyed"`, the same `key`. Enums are written as their
+//! version followed by the enum, rather than with the version next to the variant; enums with a `tag`
+//! or `untagged` still need a self-describing format, as they do without a version. So do payloads
+//! written without a version (`missing`) and fields listed in `removed`.
+//!
+//! ## Peeking the version
+//!
+//! `serde_versions::peek_version::<S, _>(deserializer)` reads only the version of a payload written by
+//! `S`, skipping everything else, so a message can be routed by its version before choosing the type
+//! to deserialize it as. Older versions have to keep the version where `S` puts it.
+//! ```ignore
+//! let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+//! match serde_versions::peek_version::<S, _>(&mut deserializer)? {
+//!     Version::Number(3) => handle(serde_json::from_slice::<S>(bytes)?),
+//!     version => return Err(Unsupported(version)),
+//! }
+//! ```
 //!  
 
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 
-use syn::{parse::Parser, parse_macro_input, DeriveInput, LitInt};
+use syn::{parse_macro_input, DeriveInput};
 
-/// Generate a new struct with a version field and ensure this struct is converted to that form before
-/// serialization.
+mod args;
+mod attrs;
+mod de;
+mod downgrade;
+mod envelope;
+mod frame;
+mod history;
+mod ser;
+
+use args::{Layout, VersionArgs};
+
+/// Generate a new struct with a version field and ensure this struct or enum is converted to that form
+/// before serialization.
 ///
 /// See crate doc for example.
 ///
 #[proc_macro_attribute]
 pub fn version(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let original_ast = parse_macro_input!(item as DeriveInput);
+    let mut original_ast = parse_macro_input!(item as DeriveInput);
+    let mut args = parse_macro_input!(attr as VersionArgs);
+    if let Err(err) = validate(&original_ast, &args) {
+        return err.to_compile_error().into();
+    }
+    if let Err(err) = history::apply(&mut original_ast, &mut args) {
+        return err.to_compile_error().into();
+    }
+    if let Some(forward) = &args.forward {
+        if let Err(err) = add_extra(&mut original_ast, forward) {
+            return err.to_compile_error().into();
+        }
+    }
 
     let mut versioned_ast = original_ast.clone();
 
-    let (impl_generics, generics, _) = original_ast.generics.split_for_impl();
-    let version = parse_macro_input!(attr as LitInt);
+    let original_generics = original_ast.generics.clone();
+    let (impl_generics, generics, where_clause) = original_generics.split_for_impl();
+    let version = &args.version;
+    let owned_version = args.owned_version();
+    let expected = version.to_version();
+    let version_ty = args.repr.ty();
+    let version_ref_ty = args.repr.ref_ty();
+    let field = &args.field;
     let struct_name = original_ast.ident.clone();
+    // used by `TryFrom<versioned>`, reading versions the same way deserializing does
+    let check_version = |found: proc_macro2::TokenStream| {
+        let into_version = args.repr.into_version();
+        let compat = args.compat.to_runtime();
+        quote! {
+            let found = #into_version(#found);
+            if !#compat.accepts(&#expected, &found) {
+                return Err(::serde_versions::VersionError::Mismatch {
+                    type_name: stringify!(#struct_name),
+                    expected: #expected,
+                    found,
+                });
+            }
+        }
+    };
+    // with `forward`, fields read from a newer version are written back under that version
+    let written_version = args.forward.as_ref().map(|_| {
+        let repr = args.repr.to_runtime();
+        quote! {
+            ::serde_versions::__private::WrittenVersion::<#version_ref_ty>::new(#version, #repr, &self.extra)
+        }
+    });
+    let check_tuple_version = check_version(quote!(s.0));
+    let check_version = check_version(quote!(s.#field));
 
     // name is old struct name with V<version_number> appended
-    let versioned_name = format_ident!("_{}v{}", original_ast.ident, version.to_string());
-    let versioned_name_str = format!("{}{}", versioned_name.to_string(), quote!{#generics}.to_string());
+    let versioned_name = format_ident!("_{}v{}", original_ast.ident, version.ident_suffix());
     versioned_ast.ident = versioned_name.clone();
+    // a container `#[serde(default)]` fills the versioned struct from the original's `Default`
+    let default_path = quote!(<#versioned_name #generics>::__default).to_string();
+    let versioned_default = (matches!(args.layout, Layout::Flat)
+        && attrs::default_from(&mut versioned_ast.attrs, &default_path))
+    .then(|| {
+        quote! {
+            impl #impl_generics #versioned_name #generics #where_clause {
+                #[doc(hidden)]
+                fn __default() -> Self {
+                    <#struct_name #generics as std::default::Default>::default().into_versioned()
+                }
+            }
+        }
+    });
+
+    // serialization is hand written so it can borrow instead of converting into the versioned struct
+    let serialize_path = attrs::take_derive(&mut original_ast.attrs, "Serialize");
+    let ref_name = format_ident!("{}Ref", versioned_name);
+
+    // deserialization reads the fields straight into the original type
+    let deserialize_path = attrs::take_derive(&mut original_ast.attrs, "Deserialize");
+    let deserialize = deserialize_path.as_ref().map(|deserialize| {
+        let deserialize = de::deserialize(&original_ast, &args, deserialize);
+        let versions_enum = versions_enum(&original_ast, &args);
+        quote!(#deserialize #versions_enum)
+    });
+    // pin the serialized key, whatever the container's `rename_all`
+    let field_str = field.to_string();
+    let rename: Option<syn::Attribute> = (serialize_path.is_some() || deserialize_path.is_some())
+        .then(|| syn::parse_quote!(#[serde(rename = #field_str)]));
+
+    // the value written with its own layout, by the envelope and keyed layouts and by framing
+    let data_impls = args.data_impls().then(|| {
+        envelope::data_impls(
+            &original_ast,
+            &args,
+            serialize_path.as_ref(),
+            deserialize_path.as_ref(),
+        )
+    });
+    let frame = args.frame.map(|frame| {
+        frame::frame_impls(
+            &original_ast,
+            &args,
+            frame,
+            serialize_path.as_ref(),
+            deserialize_path.as_ref(),
+        )
+    });
+
+    // writing the value as an older version
+    let downgrade = serialize_path
+        .as_ref()
+        .map(|_| downgrade::downgrade_impl(&original_ast, &args));
+
+    // lets code be generic over versioned types, next to the inherent `into_versioned`
+    let versioned_impl = quote! {
+        #versioned_default
+
+        impl #impl_generics ::serde_versions::Versioned for #struct_name #generics #where_clause {
+            const VERSION: ::serde_versions::Version = #expected;
+
+            type Versioned = #versioned_name #generics;
+
+            fn into_versioned(self) -> #versioned_name #generics {
+                Self::into_versioned(self)
+            }
+
+            fn try_from_versioned(versioned: #versioned_name #generics) -> Result<Self, ::serde_versions::VersionError> {
+                <Self as std::convert::TryFrom<#versioned_name #generics>>::try_from(versioned)
+            }
+        }
+
+        #downgrade
+    };
+
+    // enveloped and keyed values keep their own layout, so every shape is versioned the same way
+    if !matches!(args.layout, Layout::Flat) {
+        attrs::strip_serde(&mut original_ast);
+
+        let content = match &args.layout {
+            Layout::Envelope { content } => content.clone(),
+            _ => format_ident!("data"),
+        };
+        let content_str = content.to_string();
+        let vis = &original_ast.vis;
+        let data_ty = quote!(#struct_name #generics);
+        let serialize_data = quote!(::serde_versions::__private::SerializeData);
+        let deserialize_data = quote!(::serde_versions::__private::DeserializeData<'de>);
+        let data_attr = rename.as_ref().map(|_| {
+            let serialize_bound = quote!(#data_ty: #serialize_data).to_string();
+            let deserialize_bound = quote!(#data_ty: #deserialize_data).to_string();
+            quote! {
+                #[serde(
+                    rename = #content_str,
+                    with = "::serde_versions::__private::data",
+                    bound(serialize = #serialize_bound, deserialize = #deserialize_bound)
+                )]
+            }
+        });
+        let version_value = match &written_version {
+            Some(written_version) => quote!(#written_version),
+            None => quote! {{
+                let version: #version_ref_ty = #version;
+                version
+            }},
+        };
+        let serialize_body = match &args.layout {
+            Layout::Keyed { key } => {
+                let key = match &written_version {
+                    Some(written_version) => {
+                        let key = key.to_runtime();
+                        quote!(&#written_version.key(#key))
+                    }
+                    None => {
+                        let key = key.format(version);
+                        quote!(#key)
+                    }
+                };
+                quote!(::serde_versions::__private::Keyed { key: #key, data: self })
+            }
+            _ => quote!(::serde_versions::__private::Envelope {
+                name: stringify!(#struct_name),
+                field: #field_str,
+                version: #version_value,
+                content: #content_str,
+                data: self,
+            }),
+        };
+        let serialize = serialize_path.as_ref().map(|_| {
+            let bound = match attrs::serde_bound(&versioned_ast.attrs, "serialize") {
+                Some(bound) => quote!(#(#bound,)*),
+                None => quote!(#data_ty: #serialize_data,),
+            };
+            ser::serialize_impl(&original_ast, bound, serialize_body)
+        });
+
+        // the envelope derives serde for the versioned struct, the keyed layout implements it by hand
+        let (field_attrs, data_attr, versioned_impls) = match &args.layout {
+            Layout::Keyed { key } => {
+                let serialize = attrs::take_derive(&mut versioned_ast.attrs, "Serialize");
+                let deserialize = attrs::take_derive(&mut versioned_ast.attrs, "Deserialize");
+                let key = key.to_runtime();
+                let where_predicates: Vec<_> = where_clause
+                    .map(|w| w.predicates.iter().collect())
+                    .unwrap_or_default();
+                let serialize = serialize.map(|_| quote! {
+                    impl #impl_generics ::serde_versions::__private::serde::Serialize for #versioned_name #generics
+                    where
+                        #(#where_predicates,)*
+                        #data_ty: #serialize_data,
+                    {
+                        fn serialize<__S>(&self, serializer: __S) -> Result<__S::Ok, __S::Error>
+                        where
+                            __S: ::serde_versions::__private::serde::Serializer,
+                        {
+                            ::serde_versions::__private::serde::Serialize::serialize(
+                                &::serde_versions::__private::Keyed {
+                                    key: &#key.format(&self.#field),
+                                    data: &self.#content,
+                                },
+                                serializer,
+                            )
+                        }
+                    }
+                });
+                let deserialize = deserialize.map(|_| {
+                    let mut de_generics = original_ast.generics.clone();
+                    de_generics.params.insert(0, syn::parse_quote!('de));
+                    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+                    quote! {
+                        impl #de_impl_generics ::serde_versions::__private::serde::Deserialize<'de> for #versioned_name #generics
+                        where
+                            #(#where_predicates,)*
+                            #data_ty: #deserialize_data,
+                        {
+                            fn deserialize<__D>(deserializer: __D) -> Result<Self, __D::Error>
+                            where
+                                __D: ::serde_versions::__private::serde::Deserializer<'de>,
+                            {
+                                let (#field, #content) = ::serde_versions::__private::deserialize_keyed_versioned(
+                                    deserializer,
+                                    stringify!(#struct_name),
+                                    #key,
+                                )?;
+                                Ok(#versioned_name { #field, #content })
+                            }
+                        }
+                    }
+                });
+                (None, None, quote!(#serialize #deserialize))
+            }
+            _ => (rename.as_ref(), data_attr, quote!()),
+        };
+        let derives = versioned_ast
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("derive"));
+
+        return (quote! {
+            #original_ast
+
+            #(#derives)*
+            #vis struct #versioned_name #impl_generics #where_clause {
+                #field_attrs
+                #field: #version_ty,
+                #data_attr
+                #content: #struct_name #generics,
+            }
+
+            #versioned_impls
+
+            #data_impls
+
+            #frame
+
+            #versioned_impl
+
+            #serialize
+
+            #deserialize
+
+            impl #impl_generics #struct_name #generics #where_clause {
+                pub fn into_versioned(self) -> #versioned_name #generics {
+                    #versioned_name {
+                        #field: #owned_version,
+                        #content: self,
+                    }
+                }
+            }
+
+            impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics #where_clause {
+                fn from(s: #struct_name #generics) -> #versioned_name #generics {
+                    s.into_versioned()
+                }
+            }
+
+            impl #impl_generics std::convert::TryFrom<#versioned_name #generics> for #struct_name #generics #where_clause {
+                type Error = ::serde_versions::VersionError;
+
+                fn try_from(s: #versioned_name #generics) -> Result<#struct_name #generics, Self::Error> {
+                    #check_version
+                    Ok(s.#content)
+                }
+            }
+        })
+        .into();
+    }
+
+    attrs::strip_serde(&mut original_ast);
 
     match &mut versioned_ast.data {
         syn::Data::Struct(ref mut struct_data) => {
@@ -86,49 +742,82 @@ pub fn version(attr: TokenStream, item: TokenStream) -> TokenStream {
                     // used to convert between unversioned and versioned
                     let mut field_mapping = quote!();
                     let mut field_mapping_back = quote!();
+                    let mut field_refs = quote!();
                     for field in fields.named.iter() {
                         let name = field.ident.as_ref().unwrap();
                         field_mapping.extend(quote!(
                             #name : self . #name,
                         ));
+                        field_refs.extend(quote!(
+                            #name : & self . #name,
+                        ));
                         field_mapping_back.extend(quote!(
                             #name : s . #name,
                         ));
                     }
 
-                    fields.named.insert(
-                        0,
-                        syn::Field::parse_named
-                            .parse2(quote! { version: u8 })
-                            .unwrap(),
-                    );
+                    fields.named.insert(0, version_field(Some(field), rename.as_ref(), &version_ty));
+
+                    let serialize = serialize_path.as_ref().map(|serialize| {
+                        let (ref_version_ty, ref_version) = match &written_version {
+                            Some(written_version) => {
+                                let lifetime = ser::ref_lifetime();
+                                (
+                                    quote!(::serde_versions::__private::WrittenVersion<#lifetime, #version_ref_ty>),
+                                    written_version.clone(),
+                                )
+                            }
+                            None => (version_ref_ty.clone(), quote!(#version)),
+                        };
+                        let ref_ast = ser::borrowed(&versioned_ast, &ref_name, serialize, Some(ref_version_ty));
+                        let turbofish = ser::turbofish(&ref_ast.generics);
+                        let bound = ser::serialize_bound(&ref_ast.attrs, &ref_name, &ref_ast.generics);
+                        let serialize_impl = ser::serialize_impl(
+                            &original_ast,
+                            bound,
+                            quote!(#ref_name #turbofish { #field: #ref_version, #field_refs }),
+                        );
+                        quote!(#ref_ast #serialize_impl)
+                    });
 
                     (quote! {
-                        #[serde(into = #versioned_name_str, from = #versioned_name_str)]
                         #original_ast
 
                         #versioned_ast
 
-                        impl #impl_generics #struct_name #generics {
+                        #serialize
+
+                        #deserialize
+
+                        #data_impls
+
+                        #frame
+
+                        #versioned_impl
+
+                        impl #impl_generics #struct_name #generics #where_clause {
                             pub fn into_versioned(self) -> #versioned_name #generics {
                                 #versioned_name {
-                                    version: #version,
+                                    #field: #owned_version,
                                     #field_mapping
                                 }
                             }
                         }
 
-                        impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics {
+                        impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics #where_clause {
                             fn from(s: #struct_name #generics) -> #versioned_name #generics {
                                 s.into_versioned()
                             }
                         }
 
-                        impl #impl_generics std::convert::From<#versioned_name #generics> for #struct_name #generics {
-                            fn from(s: #versioned_name #generics) -> #struct_name #generics {
-                                #struct_name {
+                        impl #impl_generics std::convert::TryFrom<#versioned_name #generics> for #struct_name #generics #where_clause {
+                            type Error = ::serde_versions::VersionError;
+
+                            fn try_from(s: #versioned_name #generics) -> Result<#struct_name #generics, Self::Error> {
+                                #check_version
+                                Ok(#struct_name {
                                     #field_mapping_back
-                                }
+                                })
                             }
                         }
                     })
@@ -139,61 +828,581 @@ pub fn version(attr: TokenStream, item: TokenStream) -> TokenStream {
                      // used to convert between unversioned and versioned
                     let mut field_mapping = quote!();
                     let mut field_mapping_back = quote!();
+                    let mut field_refs = quote!();
                     for (i, _) in fields.unnamed.iter().enumerate() {
                         let index = syn::Index::from(i);
                         let versioned_index = syn::Index::from(i+1);
                         field_mapping.extend(quote!(
                             self . #index,
                         ));
+                        field_refs.extend(quote!(
+                            & self . #index,
+                        ));
                         field_mapping_back.extend(quote!(
                             s . #versioned_index,
                         ));
                     }
 
-                    fields.unnamed.insert(
-                        0,
-                        syn::Field::parse_unnamed
-                            .parse2(quote! { u8 })
-                            .unwrap(),
-                    );
+                    fields.unnamed.insert(0, version_field(None, None, &version_ty));
+
+                    let serialize = serialize_path.as_ref().map(|serialize| {
+                        let ref_ast = ser::borrowed(&versioned_ast, &ref_name, serialize, Some(version_ref_ty.clone()));
+                        let turbofish = ser::turbofish(&ref_ast.generics);
+                        let bound = ser::serialize_bound(&ref_ast.attrs, &ref_name, &ref_ast.generics);
+                        let serialize_impl = ser::serialize_impl(
+                            &original_ast,
+                            bound,
+                            quote!(#ref_name #turbofish(#version, #field_refs)),
+                        );
+                        quote!(#ref_ast #serialize_impl)
+                    });
 
                     (quote! {
-                        #[serde(into = #versioned_name_str, from = #versioned_name_str)]
                         #original_ast
 
                         #versioned_ast
 
-                        impl #impl_generics #struct_name #generics {
+                        #serialize
+
+                        #deserialize
+
+                        #data_impls
+
+                        #frame
+
+                        #versioned_impl
+
+                        impl #impl_generics #struct_name #generics #where_clause {
                             pub fn into_versioned(self) -> #versioned_name #generics {
                                 #versioned_name (
-                                    #version,
+                                    #owned_version,
                                     #field_mapping
                                 )
                             }
                         }
 
-                        impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics {
+                        impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics #where_clause {
                             fn from(s: #struct_name #generics) -> #versioned_name #generics {
                                 s.into_versioned()
                             }
                         }
 
-                        impl #impl_generics std::convert::From<#versioned_name #generics> for #struct_name #generics {
-                            fn from(s: #versioned_name #generics) -> #struct_name #generics {
-                                #struct_name (
+                        impl #impl_generics std::convert::TryFrom<#versioned_name #generics> for #struct_name #generics #where_clause {
+                            type Error = ::serde_versions::VersionError;
+
+                            fn try_from(s: #versioned_name #generics) -> Result<#struct_name #generics, Self::Error> {
+                                #check_tuple_version
+                                Ok(#struct_name (
                                     #field_mapping_back
-                                )
+                                ))
                             }
                         }
                     })
                     .into()                   
                 }
-                // for unit types e.g. A()
+                // for unit types e.g. A; which serialize as { version: N }
                 syn::Fields::Unit => {
-                    unimplemented!()
+                    let version_field = version_field(Some(field), rename.as_ref(), &version_ty);
+                    struct_data.fields = syn::Fields::Named(syn::parse_quote!({ #version_field }));
+
+                    // nothing to borrow, the versioned struct is serialized directly
+                    let serialize = serialize_path.as_ref().map(|_| {
+                        let turbofish = ser::turbofish(&original_ast.generics);
+                        let bound = ser::serialize_bound(&versioned_ast.attrs, &versioned_name, &original_ast.generics);
+                        ser::serialize_impl(&original_ast, bound, quote!(#versioned_name #turbofish { #field: #owned_version }))
+                    });
+
+                    (quote! {
+                        #original_ast
+
+                        #versioned_ast
+
+                        #serialize
+
+                        #deserialize
+
+                        #data_impls
+
+                        #frame
+
+                        #versioned_impl
+
+                        impl #impl_generics #struct_name #generics #where_clause {
+                            pub fn into_versioned(self) -> #versioned_name #generics {
+                                #versioned_name {
+                                    #field: #owned_version,
+                                }
+                            }
+                        }
+
+                        impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics #where_clause {
+                            fn from(s: #struct_name #generics) -> #versioned_name #generics {
+                                s.into_versioned()
+                            }
+                        }
+
+                        impl #impl_generics std::convert::TryFrom<#versioned_name #generics> for #struct_name #generics #where_clause {
+                            type Error = ::serde_versions::VersionError;
+
+                            fn try_from(s: #versioned_name #generics) -> Result<#struct_name #generics, Self::Error> {
+                                #check_version
+                                Ok(#struct_name)
+                            }
+                        }
+                    })
+                    .into()
+                }
+            }
+        }
+        // enums are wrapped, keeping their own serde layout next to the version field
+        syn::Data::Enum(enum_data) => {
+            let inner_name = format_ident!("{}Inner", versioned_name);
+            let derives = versioned_ast
+                .attrs
+                .iter()
+                .filter(|attr| attr.path.is_ident("derive"));
+            let vis = &original_ast.vis;
+
+            // used to convert between unversioned and versioned
+            let mut variant_mapping = quote!();
+            let mut variant_mapping_back = quote!();
+            let mut variant_refs = quote!();
+            let inner_ref_name = format_ident!("{}InnerRef", versioned_name);
+            for variant in enum_data.variants.iter() {
+                let name = &variant.ident;
+                let pattern = match &variant.fields {
+                    syn::Fields::Named(fields) => {
+                        let names = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+                        quote!({ #(#names),* })
+                    }
+                    syn::Fields::Unnamed(fields) => {
+                        let names = (0..fields.unnamed.len()).map(|i| format_ident!("f{}", i));
+                        quote!(( #(#names),* ))
+                    }
+                    syn::Fields::Unit => quote!(),
+                };
+                variant_mapping.extend(quote!(
+                    #struct_name::#name #pattern => #inner_name::#name #pattern,
+                ));
+                variant_mapping_back.extend(quote!(
+                    #inner_name::#name #pattern => #struct_name::#name #pattern,
+                ));
+                variant_refs.extend(quote!(
+                    #struct_name::#name #pattern => #inner_ref_name::#name #pattern,
+                ));
+            }
+            versioned_ast.ident = inner_name.clone();
+
+            let serialize = serialize_path.as_ref().map(|serialize| {
+                let inner_ref_ast = ser::borrowed(&versioned_ast, &inner_ref_name, serialize, None);
+                let (ref_impl_generics, ref_generics, _) = inner_ref_ast.generics.split_for_impl();
+                let turbofish = ser::turbofish(&inner_ref_ast.generics);
+                let bound = ser::serialize_bound(&inner_ref_ast.attrs, &inner_ref_name, &inner_ref_ast.generics);
+                // the wrapper is as serializable as the variants, whatever bound they were given
+                let inner_bound = quote!(#inner_ref_name #ref_generics: #serialize).to_string();
+                // flattening needs the length of the map up front in formats that aren't human
+                // readable, so they get the version followed by the enum
+                let serialize_impl = ser::serialize_impl(
+                    &original_ast,
+                    bound,
+                    quote!(::serde_versions::__private::ByFormat {
+                        readable: #ref_name #turbofish {
+                            #field: #version,
+                            inner: match self {
+                                #variant_refs
+                            },
+                        },
+                        compact: ({
+                            let version: #version_ref_ty = #version;
+                            version
+                        }, match self {
+                            #variant_refs
+                        }),
+                    }),
+                );
+                quote! {
+                    #inner_ref_ast
+
+                    #[derive(#serialize)]
+                    #[serde(bound(serialize = #inner_bound))]
+                    #vis struct #ref_name #ref_impl_generics #where_clause {
+                        #[serde(rename = #field_str)]
+                        #field: #version_ref_ty,
+                        #[serde(flatten)]
+                        inner: #inner_ref_name #ref_generics,
+                    }
+
+                    #serialize_impl
+                }
+            });
+
+            (quote! {
+                #original_ast
+
+                #versioned_ast
+
+                #serialize
+
+                #(#derives)*
+                #vis struct #versioned_name #impl_generics #where_clause {
+                    #rename
+                    #field: #version_ty,
+                    #[serde(flatten)]
+                    inner: #inner_name #generics,
+                }
+
+                #deserialize
+
+                #data_impls
+
+                #frame
+
+                #versioned_impl
+
+                impl #impl_generics #struct_name #generics #where_clause {
+                    pub fn into_versioned(self) -> #versioned_name #generics {
+                        #versioned_name {
+                            #field: #owned_version,
+                            inner: match self {
+                                #variant_mapping
+                            },
+                        }
+                    }
+                }
+
+                impl #impl_generics std::convert::From<#struct_name #generics> for #versioned_name #generics #where_clause {
+                    fn from(s: #struct_name #generics) -> #versioned_name #generics {
+                        s.into_versioned()
+                    }
+                }
+
+                impl #impl_generics std::convert::TryFrom<#versioned_name #generics> for #struct_name #generics #where_clause {
+                    type Error = ::serde_versions::VersionError;
+
+                    fn try_from(s: #versioned_name #generics) -> Result<#struct_name #generics, Self::Error> {
+                        #check_version
+                        Ok(match s.inner {
+                            #variant_mapping_back
+                        })
+                    }
+                }
+            })
+            .into()
+        }
+        syn::Data::Union(data) => {
+            let message = "`version` has to be used with structs or enums";
+            syn::Error::new(data.union_token.span, message)
+                .to_compile_error()
+                .into()
+        }
+    }
+}
+
+/// The field holding the version, named for structs with named fields
+fn version_field(
+    ident: Option<&syn::Ident>,
+    attr: Option<&syn::Attribute>,
+    ty: &proc_macro2::TokenStream,
+) -> syn::Field {
+    syn::Field {
+        attrs: attr.into_iter().cloned().collect(),
+        vis: syn::Visibility::Inherited,
+        colon_token: ident.as_ref().map(|_| Default::default()),
+        ident: ident.cloned(),
+        ty: syn::parse_quote!(#ty),
+    }
+}
+
+/// Reject input the generated code can't support, pointing at the offending tokens
+fn validate(ast: &DeriveInput, args: &VersionArgs) -> syn::Result<()> {
+    // only the flat layout puts the version next to the type's own fields
+    if let (
+        Layout::Flat,
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }),
+    ) = (&args.layout, &ast.data)
+    {
+        let clash = fields.named.iter().find(|field| {
+            field.ident.as_ref().is_some_and(|ident| *ident == args.field)
+        });
+        if let Some(field) = clash {
+            return Err(syn::Error::new_spanned(
+                &field.ident,
+                format!(
+                    "field `{}` clashes with the version field added by `#[version]`, \
+                     use `field = \"...\"` to name it differently",
+                    args.field
+                ),
+            ));
+        }
+    }
+
+    if let Some(path) = attrs::find_serde_arg(&ast.attrs, &["into", "from", "try_from"]) {
+        return Err(syn::Error::new_spanned(
+            &path,
+            format!(
+                "`#[serde({})]` conflicts with `#[version]`, which converts to and from the versioned type itself",
+                quote!(#path)
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Add the `extra` field keeping the fields of newer versions, for `forward`
+fn add_extra(ast: &mut DeriveInput, forward: &syn::Ident) -> syn::Result<()> {
+    let vis = ast.vis.clone();
+    let fields = match &mut ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields,
+        _ => {
+            return Err(syn::Error::new(
+                forward.span(),
+                "`forward` needs a struct with named fields to keep unknown fields in",
+            ))
+        }
+    };
+    if let Some(field) = fields
+        .named
+        .iter()
+        .find(|field| field.ident.as_ref().is_some_and(|ident| ident == "extra"))
+    {
+        return Err(syn::Error::new_spanned(
+            &field.ident,
+            "field `extra` clashes with the store of unknown fields added by `forward`",
+        ));
+    }
+    let extra = quote! {
+        #[serde(flatten)]
+        #vis extra: ::serde_versions::Extra
+    };
+    fields
+        .named
+        .push(syn::parse::Parser::parse2(syn::Field::parse_named, extra)?);
+    Ok(())
+}
+
+/// Generate `enum <Name>Versions { V1(SV1), V2(SV2), V3(Name) }` from the `versions(...)` argument
+fn versions_enum(original_ast: &DeriveInput, args: &VersionArgs) -> proc_macro2::TokenStream {
+    if args.versions.is_empty() {
+        return quote!();
+    }
+
+    let struct_name = &original_ast.ident;
+    let vis = &original_ast.vis;
+    let enum_name = format_ident!("{}Versions", struct_name);
+    let (impl_generics, generics, where_clause) = original_ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+    let mut de_generics = original_ast.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    let doc = format!(" Every known version of [`{}`]", struct_name);
+    let expected = args.version.to_version();
+    let latest = format_ident!("V{}", args.version.ident_suffix());
+
+    let mut variants = quote!();
+    let mut serialize_arms = quote!();
+    let mut deserialize_arms = quote!();
+    let mut seq_arms = quote!();
+    let mut entry_arms = quote!();
+    let mut stream_len = quote!(<#struct_name #generics as ::serde_versions::__private::Migrate>::STREAM.len);
+    let mut version_arms = quote!();
+    let mut into_latest_arms = quote!();
+    let mut checks = quote!();
+    let mut serialize_bounds = quote!();
+    let mut deserialize_bounds = quote!();
+    for (i, known) in args.versions.iter().enumerate() {
+        let number = &known.version;
+        let known_version = number.to_version();
+        let ty = &known.ty;
+        let variant = format_ident!("V{}", number.ident_suffix());
+
+        // upgrade through each of the newer listed versions in turn
+        let mut upgraded = quote!(v);
+        for newer in &args.versions[i + 1..] {
+            let newer_ty = &newer.ty;
+            upgraded = quote!(<#newer_ty as ::serde_versions::__private::Upgrade>::upgrade(#upgraded));
+        }
+
+        variants.extend(quote!(#variant(#ty),));
+        serialize_bounds.extend(quote!(#ty: ::serde_versions::__private::serde::Serialize,));
+        deserialize_bounds.extend(quote!(#ty: ::serde_versions::__private::Migrate,));
+        serialize_arms.extend(quote!(
+            #enum_name::#variant(v) => ::serde_versions::__private::serde::Serialize::serialize(v, serializer),
+        ));
+        deserialize_arms.extend(quote!(
+            if <#ty as ::serde_versions::__private::Migrate>::reads(&version) {
+                return <#ty as ::serde_versions::__private::Migrate>::from_content(version, content)
+                    .map(#enum_name::#variant);
+            }
+        ));
+        seq_arms.extend(quote!(
+            if <#ty as ::serde_versions::__private::Migrate>::reads(&version) {
+                return <#ty as ::serde_versions::__private::Migrate>::from_seq(version, seq)
+                    .map(#enum_name::#variant);
+            }
+        ));
+        entry_arms.extend(quote!(
+            if <#ty as ::serde_versions::__private::Migrate>::reads(&version) {
+                return <#ty as ::serde_versions::__private::Migrate>::from_entry(version, map)
+                    .map(#enum_name::#variant);
+            }
+        ));
+        stream_len = quote!(::serde_versions::__private::max_len(
+            #stream_len,
+            <#ty as ::serde_versions::__private::Migrate>::STREAM.len
+        ));
+        version_arms.extend(quote!(#enum_name::#variant(_) => #known_version,));
+        into_latest_arms.extend(quote!(
+            #enum_name::#variant(v) => <#struct_name #generics as ::serde_versions::__private::Upgrade>::upgrade(#upgraded),
+        ));
+        if original_ast.generics.params.is_empty() {
+            let message = format!("`{}` is not version {}", quote!(#ty), quote!(#number));
+            checks.extend(quote!(
+                const _: () = {
+                    const EXPECTED: &::serde_versions::Version = &#known_version;
+                    assert!(
+                        ::serde_versions::__private::version_eq(<#ty as ::serde_versions::__private::Migrate>::VERSION, EXPECTED),
+                        #message
+                    );
+                };
+            ));
+        }
+    }
+
+    quote! {
+        #[doc = #doc]
+        #vis enum #enum_name #impl_generics #where_clause {
+            #variants
+            #latest(#struct_name #generics),
+        }
+
+        #checks
+
+        impl #impl_generics #enum_name #generics #where_clause {
+            /// The version of the contained value
+            pub fn version(&self) -> ::serde_versions::Version {
+                match self {
+                    #version_arms
+                    #enum_name::#latest(_) => #expected,
+                }
+            }
+
+            /// Upgrade the contained value to the latest version
+            pub fn into_latest(self) -> #struct_name #generics {
+                match self {
+                    #into_latest_arms
+                    #enum_name::#latest(v) => v,
+                }
+            }
+        }
+
+        impl #impl_generics ::serde_versions::__private::serde::Serialize for #enum_name #generics
+        where
+            #(#where_predicates,)*
+            #serialize_bounds
+            #struct_name #generics: ::serde_versions::__private::serde::Serialize,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde_versions::__private::serde::Serializer,
+            {
+                match self {
+                    #serialize_arms
+                    #enum_name::#latest(v) => ::serde_versions::__private::serde::Serialize::serialize(v, serializer),
+                }
+            }
+        }
+
+        impl #de_impl_generics ::serde_versions::__private::serde::Deserialize<'de> for #enum_name #generics
+        where
+            #(#where_predicates,)*
+            #deserialize_bounds
+            #struct_name #generics: ::serde_versions::__private::Migrate,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde_versions::__private::serde::Deserializer<'de>,
+            {
+                if !deserializer.is_human_readable() {
+                    return ::serde_versions::__private::deserialize_stream(deserializer);
+                }
+                let content: ::serde_versions::__private::Content =
+                    ::serde_versions::__private::serde::Deserialize::deserialize(deserializer)?;
+                <Self as ::serde_versions::__private::ReadRest>::from_buffered(content)
+            }
+        }
+
+        impl #impl_generics ::serde_versions::__private::ReadRest for #enum_name #generics
+        where
+            #(#where_predicates,)*
+            #deserialize_bounds
+            #struct_name #generics: ::serde_versions::__private::Migrate,
+        {
+            const STREAM: ::serde_versions::__private::Stream = ::serde_versions::__private::Stream {
+                type_name: stringify!(#enum_name),
+                len: #stream_len,
+                ..<#struct_name #generics as ::serde_versions::__private::Migrate>::STREAM
+            };
+
+            fn from_seq<'de, __A: ::serde_versions::__private::serde::de::SeqAccess<'de>>(
+                version: ::serde_versions::Version,
+                seq: __A,
+            ) -> Result<Self, __A::Error> {
+                #seq_arms
+                if <#struct_name #generics as ::serde_versions::__private::Migrate>::reads(&version) {
+                    return <#struct_name #generics as ::serde_versions::__private::Migrate>::from_seq(version, seq)
+                        .map(#enum_name::#latest);
+                }
+                Err(::serde_versions::VersionError::Mismatch {
+                    type_name: stringify!(#enum_name),
+                    expected: #expected,
+                    found: version,
+                }
+                .into_de_error())
+            }
+
+            fn from_entry<'de, __A: ::serde_versions::__private::serde::de::MapAccess<'de>>(
+                version: ::serde_versions::Version,
+                map: &mut __A,
+            ) -> Result<Self, __A::Error> {
+                #entry_arms
+                if <#struct_name #generics as ::serde_versions::__private::Migrate>::reads(&version) {
+                    return <#struct_name #generics as ::serde_versions::__private::Migrate>::from_entry(version, map)
+                        .map(#enum_name::#latest);
+                }
+                Err(::serde_versions::VersionError::Mismatch {
+                    type_name: stringify!(#enum_name),
+                    expected: #expected,
+                    found: version,
+                }
+                .into_de_error())
+            }
+
+            fn from_buffered<__E: ::serde_versions::__private::serde::de::Error>(
+                content: ::serde_versions::__private::Content,
+            ) -> Result<Self, __E> {
+                let (version, content) =
+                    ::serde_versions::__private::versioned_content::<#struct_name #generics, _>(content)?;
+                #deserialize_arms
+                if <#struct_name #generics as ::serde_versions::__private::Migrate>::reads(&version) {
+                    return <#struct_name #generics as ::serde_versions::__private::Migrate>::from_content(version, content)
+                        .map(#enum_name::#latest);
+                }
+                Err(::serde_versions::VersionError::Mismatch {
+                    type_name: stringify!(#enum_name),
+                    expected: #expected,
+                    found: version,
                 }
+                .into_de_error())
             }
         }
-        _ => panic!("`version` has to be used with structs "),
     }
 }
@@ -0,0 +1,143 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, DeriveInput, Generics, Ident, Path};
+
+use crate::attrs::{retain_serde, serde_bound};
+
+/// Lifetime of the borrows held by the `Ref` mirrors
+pub(crate) fn ref_lifetime() -> syn::Lifetime {
+    syn::parse_quote!('__ref)
+}
+
+/// Make a serialize-only copy of `ast`, named `name`, whose fields borrow from the original.
+///
+/// When `version_ty` is given the first field of a struct is the version field, which is given that
+/// type instead of being borrowed. Only serde attributes are kept so other derives' helper
+/// attributes don't dangle.
+pub(crate) fn borrowed(
+    ast: &DeriveInput,
+    name: &Ident,
+    serialize: &Path,
+    version_ty: Option<TokenStream>,
+) -> DeriveInput {
+    let lifetime = ref_lifetime();
+    let mut borrowed = ast.clone();
+    borrowed.ident = name.clone();
+    retain_serde(&mut borrowed.attrs);
+    borrowed
+        .attrs
+        .insert(0, syn::parse_quote!(#[derive(#serialize)]));
+    // fields skipped when serializing are never read
+    borrowed
+        .attrs
+        .insert(0, syn::parse_quote!(#[allow(dead_code)]));
+
+    let mut borrows = false;
+    let mut borrow_fields = |fields: &mut syn::Fields, version_ty: Option<TokenStream>| {
+        let skip = match version_ty {
+            Some(ty) => {
+                if let Some(field) = fields.iter_mut().next() {
+                    field.ty = syn::parse_quote!(#ty);
+                }
+                1
+            }
+            None => 0,
+        };
+        for field in fields.iter_mut().skip(skip) {
+            let ty = &field.ty;
+            field.ty = syn::parse_quote!(&#lifetime #ty);
+            borrows = true;
+        }
+        for field in fields.iter_mut() {
+            retain_serde(&mut field.attrs);
+        }
+    };
+    match &mut borrowed.data {
+        syn::Data::Struct(data) => borrow_fields(&mut data.fields, version_ty),
+        syn::Data::Enum(data) => {
+            for variant in data.variants.iter_mut() {
+                retain_serde(&mut variant.attrs);
+                borrow_fields(&mut variant.fields, None);
+            }
+        }
+        syn::Data::Union(_) => {}
+    }
+
+    if borrows {
+        borrowed
+            .generics
+            .params
+            .insert(0, syn::parse_quote!(#lifetime));
+    }
+    borrowed
+}
+
+/// Turbofish naming a `Ref` mirror from inside a method of the original type, e.g. `::<'_, T, N>`
+pub(crate) fn turbofish(generics: &syn::Generics) -> TokenStream {
+    let lifetime = ref_lifetime();
+    let args = generics.params.iter().map(|param| match param {
+        syn::GenericParam::Lifetime(def) if def.lifetime == lifetime => quote!('_),
+        syn::GenericParam::Lifetime(def) => {
+            let lifetime = &def.lifetime;
+            quote!(#lifetime)
+        }
+        syn::GenericParam::Type(param) => {
+            let ident = &param.ident;
+            quote!(#ident)
+        }
+        syn::GenericParam::Const(param) => {
+            let ident = &param.ident;
+            quote!(#ident)
+        }
+    });
+    quote!(::<#(#args),*>)
+}
+
+/// Where-predicates of a `Serialize` impl writing a `name<generics>`: the container's
+/// `#[serde(bound(serialize = "..."))]` when given, otherwise that type being `Serialize` for any
+/// lifetime of its borrows.
+pub(crate) fn serialize_bound(
+    attrs: &[Attribute],
+    name: &Ident,
+    generics: &Generics,
+) -> TokenStream {
+    if let Some(bound) = serde_bound(attrs, "serialize") {
+        return quote!(#(#bound,)*);
+    }
+    let lifetime = ref_lifetime();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let for_lifetime = generics
+        .lifetimes()
+        .any(|def| def.lifetime == lifetime)
+        .then(|| quote!(for<#lifetime>));
+    quote!(#for_lifetime #name #ty_generics: ::serde_versions::__private::serde::Serialize,)
+}
+
+/// `impl Serialize for #ast` by serializing the value produced by `body`, which can borrow `self`,
+/// where `bound` holds.
+pub(crate) fn serialize_impl(
+    ast: &DeriveInput,
+    bound: TokenStream,
+    body: TokenStream,
+) -> TokenStream {
+    let name = &ast.ident;
+    let (impl_generics, generics, where_clause) = ast.generics.split_for_impl();
+    let where_predicates: Vec<_> = where_clause
+        .map(|w| w.predicates.iter().collect())
+        .unwrap_or_default();
+
+    quote! {
+        impl #impl_generics ::serde_versions::__private::serde::Serialize for #name #generics
+        where
+            #(#where_predicates,)*
+            #bound
+        {
+            fn serialize<__S>(&self, serializer: __S) -> Result<__S::Ok, __S::Error>
+            where
+                __S: ::serde_versions::__private::serde::Serializer,
+            {
+                ::serde_versions::__private::serde::Serialize::serialize(&#body, serializer)
+            }
+        }
+    }
+}
@@ -1,5 +1,12 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_versions::{
+    peek_version, Codec, Downgrade, FrameError, Version, VersionError, Versioned,
+};
 use serde_versions_derive::version;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::time::Duration;
 
 #[version(3)]
 #[derive(Clone, Serialize, Deserialize)]
@@ -44,3 +51,1770 @@ fn to_json_has_version_unnamed() {
     let json_s: serde_json::Value = serde_json::from_str(&json_str_s).unwrap();
     assert_eq!(json_s[0], 33);
 }
+
+#[test]
+fn from_json_round_trip() {
+    let s = S {
+        i: 0,
+        b: true,
+        o: Some(8),
+    };
+    let json_str_s = serde_json::to_string(&s).unwrap();
+    let s2: S = serde_json::from_str(&json_str_s).unwrap();
+    assert_eq!(s2.i, 0);
+    assert!(s2.b);
+    assert_eq!(s2.o, Some(8));
+}
+
+#[test]
+fn from_json_wrong_version_fails() {
+    let err = serde_json::from_str::<S>(r#"{"version":7,"i":0,"b":true,"o":null}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("expected version 3, found 7"));
+}
+
+#[test]
+fn from_json_wrong_version_fails_unnamed() {
+    let err = serde_json::from_str::<SS<i32>>("[7,123]").err().unwrap();
+    assert!(err.to_string().contains("expected version 33, found 7"));
+}
+
+#[test]
+fn try_from_versioned_reports_mismatch() {
+    let mut v = SS(123).into_versioned();
+    v.0 = 7;
+    match SS::try_from(v) {
+        Err(VersionError::Mismatch {
+            type_name,
+            expected,
+            found,
+        }) => {
+            assert_eq!(type_name, "SS");
+            assert_eq!(expected, Version::Number(33));
+            assert_eq!(found, Version::Number(7));
+        }
+        _ => panic!("expected a version mismatch"),
+    }
+}
+
+#[version(1)]
+#[derive(Clone, Serialize, Deserialize)]
+struct Point1 {
+    x: i32,
+}
+
+#[version(2, previous = Point1)]
+#[derive(Clone, Serialize, Deserialize)]
+struct Point2 {
+    x: i32,
+    y: i32,
+}
+
+impl From<Point1> for Point2 {
+    fn from(p: Point1) -> Self {
+        Point2 { x: p.x, y: 0 }
+    }
+}
+
+#[version(3, previous = Point2, upgrade = point3_from_point2)]
+#[derive(Clone, Serialize, Deserialize)]
+struct Point3 {
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+fn point3_from_point2(p: Point2) -> Point3 {
+    Point3 {
+        x: p.x.into(),
+        y: p.y.into(),
+        z: 0,
+    }
+}
+
+#[test]
+fn migrates_through_previous_versions() {
+    let p: Point3 = serde_json::from_str(r#"{"version":1,"x":5}"#).unwrap();
+    assert_eq!((p.x, p.y, p.z), (5, 0, 0));
+
+    let p: Point3 = serde_json::from_str(r#"{"version":2,"x":5,"y":6}"#).unwrap();
+    assert_eq!((p.x, p.y, p.z), (5, 6, 0));
+
+    let p: Point3 = serde_json::from_str(r#"{"version":3,"x":5,"y":6,"z":7}"#).unwrap();
+    assert_eq!((p.x, p.y, p.z), (5, 6, 7));
+}
+
+#[test]
+fn migration_rejects_unknown_versions() {
+    let err = serde_json::from_str::<Point3>(r#"{"version":9,"x":5}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Point3: expected version 3, found 9"));
+
+    let err = serde_json::from_str::<Point3>(r#"{"x":5}"#).err().unwrap();
+    assert!(err.to_string().contains("missing version"));
+}
+
+#[test]
+fn reads_older_versions_from_bincode() {
+    let bytes = bincode::serialize(&Point1 { x: 5 }).unwrap();
+    let p: Point3 = bincode::deserialize(&bytes).unwrap();
+    assert_eq!((p.x, p.y, p.z), (5, 0, 0));
+
+    let bytes = bincode::serialize(&Point2 { x: 5, y: 6 }).unwrap();
+    let p: Point3 = bincode::deserialize(&bytes).unwrap();
+    assert_eq!((p.x, p.y, p.z), (5, 6, 0));
+    let p: Point2 = bincode::deserialize(&bytes).unwrap();
+    assert_eq!((p.x, p.y), (5, 6));
+
+    let bytes = bincode::serialize(&Point3 { x: 1, y: 2, z: 3 }).unwrap();
+    let err = bincode::deserialize::<Point2>(&bytes).err().unwrap();
+    assert!(err.to_string().contains("Point2: expected version 2, found 3"));
+}
+
+#[version(4, versions(1 = Point1, 2 = Point2, 3 = Point3))]
+#[derive(Clone, Serialize, Deserialize)]
+struct Point4 {
+    x: i64,
+    y: i64,
+    z: i64,
+    w: i64,
+}
+
+impl From<Point3> for Point4 {
+    fn from(p: Point3) -> Self {
+        Point4 {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            w: 0,
+        }
+    }
+}
+
+#[test]
+fn versions_enum_dispatches_on_version() {
+    let p: Point4Versions = serde_json::from_str(r#"{"version":1,"x":5}"#).unwrap();
+    assert!(matches!(p, Point4Versions::V1(Point1 { x: 5 })));
+    assert_eq!(p.version(), Version::Number(1));
+    let p = p.into_latest();
+    assert_eq!((p.x, p.y, p.z, p.w), (5, 0, 0, 0));
+
+    let p: Point4Versions = serde_json::from_str(r#"{"version":4,"x":1,"y":2,"z":3,"w":4}"#).unwrap();
+    assert!(matches!(p, Point4Versions::V4(_)));
+    let p = p.into_latest();
+    assert_eq!((p.x, p.y, p.z, p.w), (1, 2, 3, 4));
+
+    // previous defaults to the newest listed version
+    let p: Point4 = serde_json::from_str(r#"{"version":2,"x":5,"y":6}"#).unwrap();
+    assert_eq!((p.x, p.y, p.z, p.w), (5, 6, 0, 0));
+}
+
+#[test]
+fn versions_enum_serializes_contained_version() {
+    let p = Point4Versions::V2(Point2 { x: 1, y: 2 });
+    let json: serde_json::Value = serde_json::to_value(&p).unwrap();
+    assert_eq!(json["version"], 2);
+
+    let err = serde_json::from_str::<Point4Versions>(r#"{"version":9}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Point4Versions: expected version 4, found 9"));
+}
+
+#[test]
+fn versions_enum_reads_bincode() {
+    let bytes = bincode::serialize(&Point1 { x: 5 }).unwrap();
+    let p: Point4Versions = bincode::deserialize(&bytes).unwrap();
+    assert!(matches!(p, Point4Versions::V1(Point1 { x: 5 })));
+
+    let bytes = bincode::serialize(&Point2 { x: 5, y: 6 }).unwrap();
+    let p: Point4 = bincode::deserialize(&bytes).unwrap();
+    assert_eq!((p.x, p.y, p.z, p.w), (5, 6, 0, 0));
+
+    let p = Point4 { x: 1, y: 2, z: 3, w: 4 };
+    let p: Point4Versions = bincode::deserialize(&bincode::serialize(&p).unwrap()).unwrap();
+    assert!(matches!(p, Point4Versions::V4(Point4 { w: 4, .. })));
+    assert_eq!(bincode::serialize(&p).unwrap(), bincode::serialize(&p.into_latest()).unwrap());
+}
+
+#[version(1)]
+#[derive(Clone, Serialize, Deserialize)]
+struct Marker;
+
+#[test]
+fn unit_struct_round_trip() {
+    let json_str = serde_json::to_string(&Marker).unwrap();
+    assert_eq!(json_str, r#"{"version":1}"#);
+    let _: Marker = serde_json::from_str(&json_str).unwrap();
+
+    let err = serde_json::from_str::<Marker>(r#"{"version":2}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("expected version 1, found 2"));
+}
+
+#[version(1)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum External {
+    A { x: i32 },
+    B(i32),
+    C,
+}
+
+#[version(2)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Internal {
+    A { x: i32 },
+    C,
+}
+
+#[version(3)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
+enum Adjacent {
+    A { x: i32 },
+    B(i32),
+    C,
+}
+
+#[test]
+fn externally_tagged_enum_round_trip() {
+    for e in [External::A { x: 1 }, External::B(2), External::C] {
+        let json: serde_json::Value = serde_json::to_value(&e).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(serde_json::from_value::<External>(json).unwrap(), e);
+    }
+    let json: serde_json::Value = serde_json::to_value(External::B(2)).unwrap();
+    assert_eq!(json, serde_json::json!({"version": 1, "B": 2}));
+}
+
+#[test]
+fn internally_tagged_enum_round_trip() {
+    let json_str = serde_json::to_string(&Internal::A { x: 1 }).unwrap();
+    assert_eq!(json_str, r#"{"version":2,"type":"A","x":1}"#);
+    for e in [Internal::A { x: 1 }, Internal::C] {
+        let json: serde_json::Value = serde_json::to_value(&e).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(serde_json::from_value::<Internal>(json).unwrap(), e);
+    }
+
+    let err = serde_json::from_str::<Internal>(r#"{"version":1,"type":"C"}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("expected version 2, found 1"));
+}
+
+#[test]
+fn adjacently_tagged_enum_round_trip() {
+    let json_str = serde_json::to_string(&Adjacent::B(2)).unwrap();
+    assert_eq!(json_str, r#"{"version":3,"t":"B","c":2}"#);
+    for e in [Adjacent::A { x: 1 }, Adjacent::B(2), Adjacent::C] {
+        let json: serde_json::Value = serde_json::to_value(&e).unwrap();
+        assert_eq!(json["version"], 3);
+        assert_eq!(serde_json::from_value::<Adjacent>(json).unwrap(), e);
+    }
+}
+
+#[version(4, previous = External)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum ExternalNext {
+    A { x: i64 },
+    B(i64),
+}
+
+impl From<External> for ExternalNext {
+    fn from(e: External) -> Self {
+        match e {
+            External::A { x } => ExternalNext::A { x: x.into() },
+            External::B(b) => ExternalNext::B(b.into()),
+            External::C => ExternalNext::B(0),
+        }
+    }
+}
+
+#[test]
+fn enums_round_trip_in_bincode() {
+    for e in [External::A { x: 1 }, External::B(2), External::C] {
+        let bytes = bincode::serialize(&e).unwrap();
+        assert_eq!(bincode::deserialize::<External>(&bytes).unwrap(), e);
+    }
+
+    let bytes = bincode::serialize(&External::B(2)).unwrap();
+    assert_eq!(bincode::deserialize::<ExternalNext>(&bytes).unwrap(), ExternalNext::B(2));
+    let err = bincode::deserialize::<Adjacent>(&bytes).err().unwrap();
+    assert!(err.to_string().contains("expected version 3, found 1"));
+    let bytes = bincode::serialize(&ExternalNext::A { x: 3 }).unwrap();
+    assert_eq!(bincode::deserialize::<ExternalNext>(&bytes).unwrap(), ExternalNext::A { x: 3 });
+
+    // human readable formats keep the version next to the variant
+    let json: serde_json::Value = serde_json::to_value(ExternalNext::B(4)).unwrap();
+    assert_eq!(json, serde_json::json!({"version": 4, "B": 4}));
+}
+
+trait Named {
+    fn name() -> &'static str;
+}
+
+impl Named for i32 {
+    fn name() -> &'static str {
+        "i32"
+    }
+}
+
+#[version(4)]
+#[derive(Clone, Serialize, Deserialize)]
+struct WithWhere<T>
+where
+    T: Clone + Named,
+{
+    t: T,
+}
+
+impl<T: Clone + Named> WithWhere<T> {
+    fn type_name(&self) -> &'static str {
+        T::name()
+    }
+}
+
+// only the declared bounds are known here, so the generated impls have to carry them
+fn through_versioned<T: Clone + Named>(s: WithWhere<T>) -> WithWhere<T> {
+    let back = WithWhere::try_from(s.into_versioned()).unwrap();
+    let versioned: _WithWherev4<T> = back.into();
+    WithWhere::try_from(versioned).unwrap()
+}
+
+#[version(5)]
+#[derive(Clone, Serialize, Deserialize)]
+struct WithWhereUnnamed<T>(T)
+where
+    T: Clone + Named;
+
+#[version(6)]
+#[derive(Clone, Serialize, Deserialize)]
+struct WithLifetime<'a> {
+    s: std::borrow::Cow<'a, str>,
+}
+
+#[version(7)]
+#[derive(Clone, Serialize, Deserialize)]
+struct WithConst<const N: usize> {
+    a: Vec<u8>,
+}
+
+impl<const N: usize> WithConst<N> {
+    fn is_full(&self) -> bool {
+        self.a.len() == N
+    }
+}
+
+#[version(8)]
+#[derive(Clone, Serialize, Deserialize)]
+enum WithWhereEnum<T>
+where
+    T: Clone + Named,
+{
+    A(T),
+}
+
+#[test]
+fn where_clauses_are_preserved() {
+    let json_str = serde_json::to_string(&WithWhere { t: 1 }).unwrap();
+    let s: WithWhere<i32> = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(s.t, 1);
+    let s = through_versioned(s);
+    assert_eq!((s.t, s.type_name()), (1, "i32"));
+
+    let json_str = serde_json::to_string(&WithWhereUnnamed(1)).unwrap();
+    assert_eq!(json_str, "[5,1]");
+    let s: WithWhereUnnamed<i32> = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(s.0, 1);
+
+    let json_str = serde_json::to_string(&WithWhereEnum::A(1)).unwrap();
+    assert_eq!(json_str, r#"{"version":8,"A":1}"#);
+    let WithWhereEnum::<i32>::A(t) = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(t, 1);
+}
+
+#[test]
+fn lifetimes_are_preserved() {
+    let s = WithLifetime { s: "hi".into() };
+    let json_str = serde_json::to_string(&s).unwrap();
+    assert_eq!(json_str, r#"{"version":6,"s":"hi"}"#);
+    let s: WithLifetime = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(s.s, "hi");
+}
+
+#[test]
+fn const_generics_are_preserved() {
+    let s = WithConst::<3> { a: vec![1, 2, 3] };
+    let json_str = serde_json::to_string(&s).unwrap();
+    assert_eq!(json_str, r#"{"version":7,"a":[1,2,3]}"#);
+    let s: WithConst<3> = serde_json::from_str(&json_str).unwrap();
+    assert!(s.is_full());
+}
+
+struct NotClone(u32);
+
+impl Serialize for NotClone {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for NotClone {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(NotClone)
+    }
+}
+
+#[version(9)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoClone<T> {
+    big_blob: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maybe: Option<T>,
+    #[serde(rename = "nc")]
+    not_clone: NotClone,
+}
+
+#[version(10)]
+#[derive(Serialize, Deserialize)]
+enum NoCloneEnum<T> {
+    A { t: T },
+    B(NotClone),
+}
+
+#[test]
+fn serializes_without_clone() {
+    let s = NoClone::<NotClone> {
+        big_blob: vec![1, 2],
+        maybe: None,
+        not_clone: NotClone(3),
+    };
+    let json_str = serde_json::to_string(&s).unwrap();
+    assert_eq!(json_str, r#"{"version":9,"bigBlob":[1,2],"nc":3}"#);
+    let s: NoClone<NotClone> = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(s.big_blob, vec![1, 2]);
+    assert!(s.maybe.is_none());
+    assert_eq!(s.not_clone.0, 3);
+
+    let e = NoCloneEnum::A { t: NotClone(4) };
+    let json_str = serde_json::to_string(&e).unwrap();
+    assert_eq!(json_str, r#"{"version":10,"A":{"t":4}}"#);
+    match serde_json::from_str(&json_str).unwrap() {
+        NoCloneEnum::<NotClone>::A { t } => assert_eq!(t.0, 4),
+        NoCloneEnum::B(_) => panic!("expected variant A"),
+    }
+}
+
+// only ever a type argument, never serialized or deserialized
+struct Opaque;
+
+#[version(1)]
+#[derive(Serialize)]
+struct Skipping<T> {
+    name: String,
+    #[serde(skip)]
+    marker: std::marker::PhantomData<T>,
+}
+
+#[version(2)]
+#[derive(Serialize)]
+#[serde(bound(serialize = ""))]
+struct Bounded<T> {
+    name: String,
+    marker: std::marker::PhantomData<T>,
+}
+
+#[version(3, repr = "envelope")]
+#[derive(Serialize)]
+#[serde(bound(serialize = ""))]
+struct BoundedEnvelope<T> {
+    marker: std::marker::PhantomData<T>,
+}
+
+#[version(4)]
+#[derive(Serialize)]
+#[serde(bound(serialize = ""))]
+enum BoundedEnum<T> {
+    A(std::marker::PhantomData<T>),
+}
+
+#[test]
+fn serialize_follows_the_container_bounds() {
+    let s = Skipping::<Opaque> {
+        name: "a".to_string(),
+        marker: std::marker::PhantomData,
+    };
+    assert_eq!(serde_json::to_string(&s).unwrap(), r#"{"version":1,"name":"a"}"#);
+    let s = Bounded::<Opaque> {
+        name: "b".to_string(),
+        marker: std::marker::PhantomData,
+    };
+    assert_eq!(serde_json::to_string(&s).unwrap(), r#"{"version":2,"name":"b","marker":null}"#);
+    let s = BoundedEnvelope::<Opaque> {
+        marker: std::marker::PhantomData,
+    };
+    assert_eq!(serde_json::to_string(&s).unwrap(), r#"{"version":3,"data":{"marker":null}}"#);
+    let e = BoundedEnum::<Opaque>::A(std::marker::PhantomData);
+    assert_eq!(serde_json::to_string(&e).unwrap(), r#"{"version":4,"A":null}"#);
+}
+
+#[version(5)]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = ""))]
+struct BoundedRead<T> {
+    name: String,
+    marker: std::marker::PhantomData<T>,
+}
+
+#[version(6, previous = BoundedRead<T>)]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de> + Default"))]
+struct BoundedReadNext<T> {
+    name: String,
+    #[serde(default)]
+    t: T,
+}
+
+impl<T: Default> From<BoundedRead<T>> for BoundedReadNext<T> {
+    fn from(r: BoundedRead<T>) -> Self {
+        BoundedReadNext {
+            name: r.name,
+            t: T::default(),
+        }
+    }
+}
+
+#[version(7, repr = "envelope")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = ""))]
+struct BoundedReadEnvelope<T> {
+    marker: std::marker::PhantomData<T>,
+}
+
+#[test]
+fn deserialize_follows_the_container_bounds() {
+    let r: BoundedRead<Opaque> =
+        serde_json::from_str(r#"{"version":5,"name":"a","marker":null}"#).unwrap();
+    assert_eq!(r.name, "a");
+    let r: BoundedReadNext<u8> =
+        serde_json::from_str(r#"{"version":5,"name":"b","marker":null}"#).unwrap();
+    assert_eq!((r.name.as_str(), r.t), ("b", 0));
+    let r: BoundedReadNext<u8> = serde_json::from_str(r#"{"version":6,"name":"c"}"#).unwrap();
+    assert_eq!((r.name.as_str(), r.t), ("c", 0));
+    let r: BoundedReadEnvelope<Opaque> =
+        serde_json::from_str(r#"{"version":7,"data":{"marker":null}}"#).unwrap();
+    assert_eq!(r.marker, std::marker::PhantomData);
+}
+
+#[version(11)]
+#[derive(Serialize, Deserialize)]
+struct Borrowed<'a> {
+    s: &'a str,
+    #[serde(borrow)]
+    c: std::borrow::Cow<'a, str>,
+}
+
+#[test]
+fn deserializes_borrowed_data() {
+    let json_str = r#"{"version":11,"s":"hello","c":"world"}"#;
+    let b: Borrowed = serde_json::from_str(json_str).unwrap();
+    assert_eq!(b.s, "hello");
+    assert!(matches!(b.c, std::borrow::Cow::Borrowed("world")));
+    assert_eq!(serde_json::to_string(&b).unwrap(), json_str);
+}
+
+#[test]
+fn checks_version_as_soon_as_it_is_read() {
+    // the version is rejected before the malformed field after it is reached
+    let err = serde_json::from_str::<S>(r#"{"version":7,"i":"not a number"}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("S: expected version 3, found 7"));
+
+    let err = serde_json::from_str::<S>(r#"{"i":0,"b":true,"o":null}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("missing version"));
+
+    // the version does not have to come first
+    let s: S = serde_json::from_str(r#"{"i":1,"b":false,"o":null,"version":3}"#).unwrap();
+    assert_eq!(s.i, 1);
+}
+
+#[version(3)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Def {
+    a: i32,
+    b: i32,
+}
+
+impl Default for Def {
+    fn default() -> Self {
+        Def { a: 1, b: 2 }
+    }
+}
+
+#[version(3)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct DerivedDef {
+    a: i32,
+    b: i32,
+}
+
+#[test]
+fn container_default_fills_missing_fields() {
+    let json_str = r#"{"version":3,"a":5,"b":6}"#;
+    let d: Def = serde_json::from_str(json_str).unwrap();
+    assert_eq!(d, Def { a: 5, b: 6 });
+    assert_eq!(serde_json::to_string(&d).unwrap(), json_str);
+
+    let d: Def = serde_json::from_str(r#"{"version":3,"a":5}"#).unwrap();
+    assert_eq!(d, Def { a: 5, b: 2 });
+
+    let d: DerivedDef = serde_json::from_str(r#"{"version":3,"b":6}"#).unwrap();
+    assert_eq!(d, DerivedDef { a: 0, b: 6 });
+    assert_eq!(
+        serde_json::to_string(&d).unwrap(),
+        r#"{"version":3,"a":0,"b":6}"#
+    );
+}
+
+#[version(3, field = "schema_version")]
+#[derive(Serialize, Deserialize)]
+struct SchemaVersioned {
+    i: i32,
+}
+
+#[version(2, field = "_v")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartnerRecord {
+    record_id: u32,
+}
+
+#[version(1, field = "schema_version")]
+#[derive(Serialize, Deserialize)]
+enum SchemaVersionedEnum {
+    A(i32),
+}
+
+#[test]
+fn uses_custom_field_name() {
+    let s = SchemaVersioned { i: 7 };
+    assert_eq!(s.into_versioned().schema_version, 3);
+
+    let json_str = r#"{"schema_version":3,"i":7}"#;
+    assert_eq!(serde_json::to_string(&SchemaVersioned { i: 7 }).unwrap(), json_str);
+    let s: SchemaVersioned = serde_json::from_str(json_str).unwrap();
+    assert_eq!(s.i, 7);
+    assert!(serde_json::from_str::<SchemaVersioned>(r#"{"version":3,"i":7}"#).is_err());
+
+    // the version key is not affected by `rename_all`
+    let json_str = r#"{"_v":2,"recordId":5}"#;
+    assert_eq!(serde_json::to_string(&PartnerRecord { record_id: 5 }).unwrap(), json_str);
+    let r: PartnerRecord = serde_json::from_str(json_str).unwrap();
+    assert_eq!(r.record_id, 5);
+
+    let json_str = r#"{"schema_version":1,"A":4}"#;
+    assert_eq!(serde_json::to_string(&SchemaVersionedEnum::A(4)).unwrap(), json_str);
+    let SchemaVersionedEnum::A(a) = serde_json::from_str(json_str).unwrap();
+    assert_eq!(a, 4);
+}
+
+#[version(1)]
+#[derive(Serialize, Deserialize)]
+struct Renamed1 {
+    i: i32,
+}
+
+#[version(2, field = "schema_version", previous = Renamed1)]
+#[derive(Serialize, Deserialize)]
+struct Renamed2 {
+    i: i32,
+}
+
+impl From<Renamed1> for Renamed2 {
+    fn from(r: Renamed1) -> Self {
+        Renamed2 { i: r.i }
+    }
+}
+
+#[test]
+fn migrates_from_a_differently_named_field() {
+    let r: Renamed2 = serde_json::from_str(r#"{"version":1,"i":3}"#).unwrap();
+    assert_eq!(r.i, 3);
+    let r: Renamed2 = serde_json::from_str(r#"{"schema_version":2,"i":4}"#).unwrap();
+    assert_eq!(r.i, 4);
+}
+
+#[version(70000, repr = u32)]
+#[derive(Serialize, Deserialize)]
+struct Wide {
+    i: i32,
+}
+
+#[version("2024-01")]
+#[derive(Serialize, Deserialize)]
+struct Dated {
+    i: i32,
+}
+
+#[version("2024-02")]
+#[derive(Serialize, Deserialize)]
+struct DatedTuple(i32);
+
+#[test]
+fn supports_other_version_types() {
+    let json_str = r#"{"version":70000,"i":1}"#;
+    assert_eq!(serde_json::to_string(&Wide { i: 1 }).unwrap(), json_str);
+    let w: Wide = serde_json::from_str(json_str).unwrap();
+    assert_eq!(w.i, 1);
+    let version: u32 = Wide { i: 1 }.into_versioned().version;
+    assert_eq!(version, 70000);
+
+    let json_str = r#"{"version":"2024-01","i":2}"#;
+    assert_eq!(serde_json::to_string(&Dated { i: 2 }).unwrap(), json_str);
+    let d: Dated = serde_json::from_str(json_str).unwrap();
+    assert_eq!(d.i, 2);
+    assert_eq!(Dated { i: 2 }.into_versioned().version, "2024-01");
+    let err = serde_json::from_str::<Dated>(r#"{"version":"2023-12","i":2}"#)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains(r#"Dated: expected version "2024-01", found "2023-12""#));
+    assert!(serde_json::from_str::<Dated>(r#"{"version":1,"i":2}"#).is_err());
+
+    let json_str = r#"["2024-02",3]"#;
+    assert_eq!(serde_json::to_string(&DatedTuple(3)).unwrap(), json_str);
+    let d: DatedTuple = serde_json::from_str(json_str).unwrap();
+    assert_eq!(d.0, 3);
+}
+
+#[version(1)]
+#[derive(Serialize, Deserialize)]
+struct Release1 {
+    i: i32,
+}
+
+#[version("2024-01", versions(1 = Release1))]
+#[derive(Serialize, Deserialize)]
+struct Release {
+    i: i32,
+}
+
+impl From<Release1> for Release {
+    fn from(r: Release1) -> Self {
+        Release { i: r.i }
+    }
+}
+
+#[test]
+fn migrates_from_numbers_to_strings() {
+    let r: Release = serde_json::from_str(r#"{"version":1,"i":4}"#).unwrap();
+    assert_eq!(r.i, 4);
+
+    let v: ReleaseVersions = serde_json::from_str(r#"{"version":1,"i":5}"#).unwrap();
+    assert_eq!(v.version(), Version::Number(1));
+    let v: ReleaseVersions = serde_json::from_str(r#"{"version":"2024-01","i":6}"#).unwrap();
+    assert_eq!(v.version(), Version::from("2024-01"));
+    assert_eq!(v.into_latest().i, 6);
+}
+
+#[version("1.4.0")]
+#[derive(Serialize, Deserialize)]
+struct Semver {
+    i: i32,
+}
+
+#[version("1.4.0", compat = "major")]
+#[derive(Serialize, Deserialize)]
+struct SemverMajor {
+    i: i32,
+}
+
+#[version("1.4.0", compat = "exact")]
+#[derive(Serialize, Deserialize)]
+struct SemverExact {
+    i: i32,
+}
+
+#[test]
+fn reads_compatible_semantic_versions() {
+    let payload = |version: &str| format!(r#"{{"version":"{}","i":1}}"#, version);
+
+    assert_eq!(serde_json::to_string(&Semver { i: 1 }).unwrap(), payload("1.4.0"));
+    for version in ["1.0.0", "1.3.7", "1.4.0", "1.4.9"] {
+        assert!(serde_json::from_str::<Semver>(&payload(version)).is_ok(), "{}", version);
+    }
+    for version in ["1.5.0", "0.4.0", "2.0.0", "1.4", "1.4.0-beta"] {
+        assert!(serde_json::from_str::<Semver>(&payload(version)).is_err(), "{}", version);
+    }
+    let err = serde_json::from_str::<Semver>(&payload("2.0.0")).err().unwrap();
+    assert!(err.to_string().contains("Semver: expected version 1.4.0, found 2.0.0"));
+
+    assert!(serde_json::from_str::<SemverMajor>(&payload("1.9.0")).is_ok());
+    assert!(serde_json::from_str::<SemverMajor>(&payload("2.0.0")).is_err());
+    assert!(serde_json::from_str::<SemverExact>(&payload("1.4.0")).is_ok());
+    assert!(serde_json::from_str::<SemverExact>(&payload("1.3.0")).is_err());
+
+    let mut older = Semver { i: 1 }.into_versioned();
+    older.version = "1.2.0".to_owned();
+    assert!(Semver::try_from(older).is_ok());
+}
+
+#[version(3, repr = "envelope", content = "data")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Enveloped {
+    record_id: u32,
+}
+
+#[version(2, repr = "envelope")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Meters(f64);
+
+#[version(1, repr = "envelope", content = "payload")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Pair(u8, String);
+
+#[version(4, repr = "envelope")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum EnvelopedEnum {
+    A { version: u8 },
+    B,
+}
+
+#[version(1, repr = "envelope")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EnvelopedGeneric<'a, T> {
+    t: T,
+    s: &'a str,
+}
+
+#[test]
+fn envelope_round_trip() {
+    let json_str = r#"{"version":3,"data":{"recordId":5}}"#;
+    let e = Enveloped { record_id: 5 };
+    assert_eq!(serde_json::to_string(&e).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Enveloped>(json_str).unwrap(), e);
+
+    let versioned = e.clone().into_versioned();
+    assert_eq!(versioned.version, 3);
+    assert_eq!(serde_json::to_string(&versioned).unwrap(), json_str);
+    assert_eq!(Enveloped::try_from(versioned).unwrap(), e);
+
+    let json_str = r#"{"version":2,"data":1.5}"#;
+    assert_eq!(serde_json::to_string(&Meters(1.5)).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Meters>(json_str).unwrap(), Meters(1.5));
+
+    let json_str = r#"{"version":1,"payload":[7,"x"]}"#;
+    let pair = Pair(7, "x".to_owned());
+    assert_eq!(serde_json::to_string(&pair).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Pair>(json_str).unwrap(), pair);
+
+    // the version key doesn't clash with the value's own fields or tags
+    let json_str = r#"{"version":4,"data":{"type":"A","version":9}}"#;
+    let a = EnvelopedEnum::A { version: 9 };
+    assert_eq!(serde_json::to_string(&a).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<EnvelopedEnum>(json_str).unwrap(), a);
+    let b: EnvelopedEnum = serde_json::from_str(r#"{"version":4,"data":{"type":"B"}}"#).unwrap();
+    assert_eq!(b, EnvelopedEnum::B);
+
+    let json_str = r#"{"version":1,"data":{"t":[1,2],"s":"borrowed"}}"#;
+    let g: EnvelopedGeneric<Vec<u8>> = serde_json::from_str(json_str).unwrap();
+    assert_eq!(g.s, "borrowed");
+    assert_eq!(serde_json::to_string(&g).unwrap(), json_str);
+}
+
+#[test]
+fn envelope_checks_version() {
+    // the data may come first, it is only read once the version has been checked
+    let e: Enveloped = serde_json::from_str(r#"{"data":{"recordId":1},"version":3}"#).unwrap();
+    assert_eq!(e.record_id, 1);
+    let err = serde_json::from_str::<Enveloped>(r#"{"data":{"other":1},"version":4}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Enveloped: expected version 3, found 4"));
+
+    let err = serde_json::from_str::<Enveloped>(r#"{"data":{"recordId":1}}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("missing version"));
+    assert!(serde_json::from_str::<Enveloped>(r#"{"version":3}"#).is_err());
+
+    let mut versioned = Meters(1.0).into_versioned();
+    versioned.version = 1;
+    assert!(Meters::try_from(versioned).is_err());
+}
+
+#[version(2, repr = "envelope", previous = Point1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EnvelopedPoint {
+    x: i32,
+}
+
+impl From<Point1> for EnvelopedPoint {
+    fn from(p: Point1) -> Self {
+        EnvelopedPoint { x: p.x }
+    }
+}
+
+#[test]
+fn migrates_into_an_envelope() {
+    let p: EnvelopedPoint = serde_json::from_str(r#"{"version":1,"x":3}"#).unwrap();
+    assert_eq!(p, EnvelopedPoint { x: 3 });
+    let p: EnvelopedPoint = serde_json::from_str(r#"{"version":2,"data":{"x":4}}"#).unwrap();
+    assert_eq!(p, EnvelopedPoint { x: 4 });
+
+    let bytes = bincode::serialize(&Point1 { x: 3 }).unwrap();
+    let p: EnvelopedPoint = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(p, EnvelopedPoint { x: 3 });
+    let bytes = bincode::serialize(&EnvelopedPoint { x: 4 }).unwrap();
+    assert_eq!(bincode::deserialize::<EnvelopedPoint>(&bytes).unwrap(), EnvelopedPoint { x: 4 });
+}
+
+#[version(1, repr = "keyed")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Keyed1 {
+    x: i32,
+}
+
+#[version(2, repr = "keyed", versions(1 = Keyed1))]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Keyed2(i32, i32);
+
+impl From<Keyed1> for Keyed2 {
+    fn from(k: Keyed1) -> Self {
+        Keyed2(k.x, 0)
+    }
+}
+
+#[version("2024-01", repr = "keyed", key = "schema/{}")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct KeyedRelease {
+    i: i32,
+}
+
+#[test]
+fn keyed_round_trip() {
+    let json_str = r#"{"v1":{"x":5}}"#;
+    let k = Keyed1 { x: 5 };
+    assert_eq!(serde_json::to_string(&k).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Keyed1>(json_str).unwrap(), k);
+
+    let versioned = k.clone().into_versioned();
+    assert_eq!(versioned.version, 1);
+    assert_eq!(serde_json::to_string(&versioned).unwrap(), json_str);
+    let versioned: _Keyed1v1 = serde_json::from_str(r#"{"v7":{"x":5}}"#).unwrap();
+    assert_eq!(versioned.version, 7);
+    assert!(Keyed1::try_from(versioned).is_err());
+
+    let json_str = r#"{"v2":[1,2]}"#;
+    assert_eq!(serde_json::to_string(&Keyed2(1, 2)).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Keyed2>(json_str).unwrap(), Keyed2(1, 2));
+
+    let json_str = r#"{"schema/2024-01":{"i":3}}"#;
+    assert_eq!(serde_json::to_string(&KeyedRelease { i: 3 }).unwrap(), json_str);
+    assert_eq!(
+        serde_json::from_str::<KeyedRelease>(json_str).unwrap(),
+        KeyedRelease { i: 3 }
+    );
+}
+
+#[test]
+fn keyed_dispatches_on_the_key() {
+    let k: Keyed2 = serde_json::from_str(r#"{"v1":{"x":5}}"#).unwrap();
+    assert_eq!(k, Keyed2(5, 0));
+
+    let v: Keyed2Versions = serde_json::from_str(r#"{"v1":{"x":5}}"#).unwrap();
+    assert_eq!(v.version(), Version::Number(1));
+    assert_eq!(serde_json::to_string(&v).unwrap(), r#"{"v1":{"x":5}}"#);
+    let v: Keyed2Versions = serde_json::from_str(r#"{"v2":[1,2]}"#).unwrap();
+    assert_eq!(v.version(), Version::Number(2));
+
+    let err = serde_json::from_str::<Keyed1>(r#"{"v2":[1,2]}"#).err().unwrap();
+    assert!(err.to_string().contains("Keyed1: expected version 1, found 2"));
+    let err = serde_json::from_str::<Keyed1>(r#"{"x":5}"#).err().unwrap();
+    assert!(err.to_string().contains("expected a version key"));
+    let err = serde_json::from_str::<Keyed1>("{}").err().unwrap();
+    assert!(err.to_string().contains("missing version"));
+    assert!(serde_json::from_str::<Keyed1>(r#"{"v1":{"x":5},"v2":[1,2]}"#).is_err());
+
+    let bytes = bincode::serialize(&Keyed1 { x: 5 }).unwrap();
+    assert_eq!(bincode::deserialize::<Keyed2>(&bytes).unwrap(), Keyed2(5, 0));
+    let v: Keyed2Versions = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(v.version(), Version::Number(1));
+    let bytes = bincode::serialize(&Keyed2(1, 2)).unwrap();
+    assert_eq!(bincode::deserialize::<Keyed2>(&bytes).unwrap(), Keyed2(1, 2));
+}
+
+#[version(1, frame = "varint")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Framed1 {
+    x: u8,
+}
+
+#[version(300, repr = u16, frame = "varint", previous = Framed1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Framed2(u8, u8);
+
+impl From<Framed1> for Framed2 {
+    fn from(f: Framed1) -> Self {
+        Framed2(f.x, 0)
+    }
+}
+
+#[version(2, repr = u16, frame = "fixed")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum FramedEnum {
+    A(u8),
+}
+
+#[version("2024-01", frame = "varint")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FramedRelease;
+
+#[test]
+fn frames_with_a_raw_version_header() {
+    let mut bytes = Vec::new();
+    Framed1 { x: 7 }.encode_versioned(&mut bytes).unwrap();
+    assert_eq!(bytes, [1, 7]);
+    assert_eq!(Framed1::decode_versioned(&bytes[..]).unwrap(), Framed1 { x: 7 });
+
+    let mut bytes = Vec::new();
+    Framed2(1, 2).encode_versioned(&mut bytes).unwrap();
+    assert_eq!(bytes, [0xac, 0x02, 1, 2]);
+    assert_eq!(Framed2::decode_versioned(&bytes[..]).unwrap(), Framed2(1, 2));
+
+    let mut bytes = Vec::new();
+    FramedEnum::A(3).encode_versioned(&mut bytes).unwrap();
+    assert_eq!(bytes, [2, 0, 0, 0, 0, 0, 3]);
+    assert_eq!(FramedEnum::decode_versioned(&bytes[..]).unwrap(), FramedEnum::A(3));
+
+    let mut bytes = Vec::new();
+    FramedRelease.encode_versioned(&mut bytes).unwrap();
+    assert_eq!(bytes, b"\x072024-01");
+    assert_eq!(FramedRelease::decode_versioned(&bytes[..]).unwrap(), FramedRelease);
+}
+
+#[test]
+fn frame_header_chooses_the_decoder() {
+    let mut bytes = Vec::new();
+    Framed1 { x: 7 }.encode_versioned(&mut bytes).unwrap();
+    assert_eq!(Framed2::decode_versioned(&bytes[..]).unwrap(), Framed2(7, 0));
+
+    let mut reader = &bytes[..];
+    let version = Framed2::read_version(&mut reader).unwrap();
+    assert_eq!(version, Version::Number(1));
+    assert_eq!(Framed1::decode_body(version, reader).unwrap(), Framed1 { x: 7 });
+
+    let mut bytes = Vec::new();
+    Framed2(1, 2).encode_versioned(&mut bytes).unwrap();
+    let err = Framed1::decode_versioned(&bytes[..]).err().unwrap();
+    assert!(err.to_string().contains("expected version 1"));
+
+    assert!(Framed1::decode_versioned(&[][..]).is_err());
+}
+
+#[version(3, frame = "varint")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FramedAccount {
+    id: u32,
+    #[versioned(since = 2)]
+    email: Option<String>,
+    #[versioned(before = 3, as = "u16")]
+    score: u32,
+}
+
+#[version(1, forward = 2, frame = "varint")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FramedForward {
+    x: u8,
+}
+
+#[test]
+fn frames_older_versions_of_a_field_history() {
+    let mut bytes = vec![1];
+    bincode::serialize_into(&mut bytes, &(5u32, 7u16)).unwrap();
+    let a = FramedAccount::decode_versioned(&bytes[..]).unwrap();
+    assert_eq!(
+        a,
+        FramedAccount {
+            id: 5,
+            email: None,
+            score: 7,
+        }
+    );
+
+    let mut bytes = vec![2];
+    bincode::serialize_into(&mut bytes, &(5u32, Some("a"), 7u16)).unwrap();
+    let a = FramedAccount::decode_versioned(&bytes[..]).unwrap();
+    assert_eq!((a.email.as_deref(), a.score), (Some("a"), 7));
+
+    let mut bytes = Vec::new();
+    a.encode_versioned(&mut bytes).unwrap();
+    assert_eq!(FramedAccount::decode_versioned(&bytes[..]).unwrap(), a);
+
+    // the fields a newer version added can't be told apart in the body
+    let err = FramedForward::decode_versioned(&[2, 1][..]).err().unwrap();
+    assert!(err.to_string().contains("FramedForward: expected version 1, found 2"));
+}
+
+/// Encodes framed bodies as JSON, in place of bincode
+struct JsonBody;
+
+impl Codec for JsonBody {
+    type Error = serde_json::Error;
+
+    fn encode<W: Write, T: Serialize + ?Sized>(
+        &self,
+        writer: W,
+        value: &T,
+    ) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, value)
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(&self, reader: R) -> serde_json::Result<T> {
+        serde_json::from_reader(reader)
+    }
+}
+
+#[test]
+fn frames_with_another_codec() {
+    let mut bytes = Vec::new();
+    Framed1 { x: 7 }
+        .encode_versioned_with(&JsonBody, &mut bytes)
+        .unwrap();
+    assert_eq!(bytes, b"\x01{\"x\":7}");
+    assert_eq!(
+        Framed1::decode_versioned_with(&JsonBody, &bytes[..]).unwrap(),
+        Framed1 { x: 7 }
+    );
+    // older versions are decoded with the same codec
+    assert_eq!(
+        Framed2::decode_versioned_with(&JsonBody, &bytes[..]).unwrap(),
+        Framed2(7, 0)
+    );
+
+    let err = Framed1::decode_versioned_with(&JsonBody, &b"\x01[]"[..])
+        .err()
+        .unwrap();
+    assert!(matches!(err, FrameError::Body(_)));
+}
+
+#[test]
+fn peeks_the_version_only() {
+    use bincode::Options;
+
+    // the rest of the payload doesn't have to match the type
+    let mut de = serde_json::Deserializer::from_str(r#"{"x":{"any":[1]},"version":7,"y":null}"#);
+    assert_eq!(peek_version::<Point1, _>(&mut de).unwrap(), Version::Number(7));
+    de.end().unwrap();
+
+    let mut de = serde_json::Deserializer::from_str(r#"[2,"anything",[]]"#);
+    assert_eq!(peek_version::<Framed2, _>(&mut de).unwrap(), Version::Number(2));
+
+    let mut de = serde_json::Deserializer::from_str(r#"{"data":{"other":1},"version":4}"#);
+    assert_eq!(peek_version::<Enveloped, _>(&mut de).unwrap(), Version::Number(4));
+
+    let mut de = serde_json::Deserializer::from_str(r#"{"schema/2023-12":{"i":"old"}}"#);
+    assert_eq!(
+        peek_version::<KeyedRelease, _>(&mut de).unwrap(),
+        Version::from("2023-12")
+    );
+
+    let mut de = serde_json::Deserializer::from_str(r#"{"x":1}"#);
+    let err = peek_version::<Point1, _>(&mut de).err().unwrap();
+    assert!(err.to_string().contains("missing version"));
+
+    let bytes = bincode::serialize(&Point2 { x: 1, y: 2 }).unwrap();
+    let version = peek_version::<Point1, _>(&mut bincode::Deserializer::from_slice(
+        &bytes,
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes(),
+    ))
+    .unwrap();
+    assert_eq!(version, Version::Number(2));
+}
+
+fn stored<T: Versioned>(value: T) -> (Version, T::Versioned) {
+    (T::VERSION, value.into_versioned())
+}
+
+#[test]
+fn versioned_trait_is_generic_over_layouts() {
+    let (version, versioned) = stored(Point2 { x: 1, y: 2 });
+    assert_eq!(version, Version::Number(2));
+    assert_eq!(versioned.version, 2);
+    let p = Point2::try_from_versioned(versioned).unwrap();
+    assert_eq!((p.x, p.y), (1, 2));
+
+    let (version, versioned) = stored(Keyed2(3, 4));
+    assert_eq!(version, Version::Number(2));
+    assert_eq!(Keyed2::try_from_versioned(versioned).unwrap(), Keyed2(3, 4));
+
+    assert_eq!(Semver::VERSION.to_string(), "1.4.0");
+    assert_eq!(<EnvelopedEnum as Versioned>::VERSION, Version::Number(4));
+
+    let mut versioned = Release::into_versioned(Release { i: 1 });
+    versioned.version = "2023-12".to_owned();
+    let err = Release::try_from_versioned(versioned).err().unwrap();
+    assert!(matches!(err, VersionError::Mismatch { .. }));
+}
+
+fn unnamed() -> String {
+    "unnamed".to_owned()
+}
+
+#[version(4)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Account {
+    id: u32,
+    #[versioned(since = 2)]
+    email: Option<String>,
+    #[versioned(since = 3, default = "unnamed")]
+    name: String,
+    #[versioned(until = 4)]
+    legacy_flags: u8,
+}
+
+#[test]
+fn fields_describe_their_history() {
+    let a: Account = serde_json::from_str(r#"{"version":1,"id":1,"legacy_flags":3}"#).unwrap();
+    assert_eq!(
+        a,
+        Account {
+            id: 1,
+            email: None,
+            name: "unnamed".to_owned(),
+            legacy_flags: 3
+        }
+    );
+    let a: Account =
+        serde_json::from_str(r#"{"version":3,"id":1,"email":"a@b.c","name":"A","legacy_flags":0}"#)
+            .unwrap();
+    assert_eq!(a.email.as_deref(), Some("a@b.c"));
+    assert_eq!(a.name, "A");
+
+    // removed fields are no longer written
+    let json_str = r#"{"version":4,"id":1,"email":null,"name":"A"}"#;
+    let a: Account = serde_json::from_str(json_str).unwrap();
+    assert_eq!(a.legacy_flags, 0);
+    assert_eq!(serde_json::to_string(&a).unwrap(), json_str);
+
+    let err = serde_json::from_str::<Account>(r#"{"version":5,"id":1}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Account: expected version 4, found 5"));
+
+    // fields are only filled in for the versions without them
+    let err = serde_json::from_str::<Account>(r#"{"version":4,"id":1,"email":null}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("missing field `name`"));
+    let json_str = r#"{"version":1,"id":1,"email":"a@b.c","legacy_flags":3}"#;
+    let err = serde_json::from_str::<Account>(json_str).err().unwrap();
+    assert!(err.to_string().contains("field `email` was added in version 2"));
+    let json_str = r#"{"version":4,"id":1,"email":null,"name":"A","legacy_flags":3}"#;
+    let err = serde_json::from_str::<Account>(json_str).err().unwrap();
+    assert!(err.to_string().contains("field `legacy_flags` was removed in version 4"));
+    let err = serde_json::from_str::<Account>(r#"{"version":2,"id":1}"#).err().unwrap();
+    assert!(err.to_string().contains("missing field `legacy_flags`"));
+}
+
+#[test]
+fn field_history_in_bincode() {
+    let a = Account {
+        id: 1,
+        email: Some("a@b.c".to_owned()),
+        name: "A".to_owned(),
+        legacy_flags: 0,
+    };
+    let bytes = bincode::serialize(&a).unwrap();
+    assert_eq!(bincode::deserialize::<Account>(&bytes).unwrap(), a);
+
+    #[derive(Serialize)]
+    struct AccountV1 {
+        version: u8,
+        id: u32,
+        legacy_flags: u8,
+    }
+    let v1 = AccountV1 {
+        version: 1,
+        id: 1,
+        legacy_flags: 3,
+    };
+    let a: Account = bincode::deserialize(&bincode::serialize(&v1).unwrap()).unwrap();
+    assert_eq!(
+        a,
+        Account {
+            id: 1,
+            email: None,
+            name: "unnamed".to_owned(),
+            legacy_flags: 3
+        }
+    );
+}
+
+#[version(3, previous = Point1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PointWithHistory {
+    x: i32,
+    #[versioned(since = 3)]
+    y: i32,
+}
+
+impl From<Point1> for PointWithHistory {
+    fn from(p: Point1) -> Self {
+        PointWithHistory { x: p.x, y: -1 }
+    }
+}
+
+#[test]
+fn history_leaves_known_versions_to_previous() {
+    let p: PointWithHistory = serde_json::from_str(r#"{"version":1,"x":1}"#).unwrap();
+    assert_eq!(p, PointWithHistory { x: 1, y: -1 });
+    let p: PointWithHistory = serde_json::from_str(r#"{"version":2,"x":1}"#).unwrap();
+    assert_eq!(p, PointWithHistory { x: 1, y: 0 });
+    let p: PointWithHistory = serde_json::from_str(r#"{"version":3,"x":1,"y":2}"#).unwrap();
+    assert_eq!(p, PointWithHistory { x: 1, y: 2 });
+}
+
+#[version(3)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Renamed {
+    #[versioned(renamed_from = "name", before = 3)]
+    full_name: String,
+    #[versioned(renamed_from = "mail", before = 2)]
+    email: Option<String>,
+}
+
+#[version(3, repr = "envelope")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EnvelopedRenamed {
+    #[versioned(renamed_from = "name", before = 2)]
+    full_name: String,
+}
+
+#[test]
+fn renamed_fields_follow_the_version() {
+    let r: Renamed = serde_json::from_str(r#"{"version":1,"name":"A","mail":"a@b.c"}"#).unwrap();
+    assert_eq!(
+        r,
+        Renamed {
+            full_name: "A".to_owned(),
+            email: Some("a@b.c".to_owned())
+        }
+    );
+    let r: Renamed = serde_json::from_str(r#"{"version":2,"name":"A","email":"x@y.z"}"#).unwrap();
+    assert_eq!(r.full_name, "A");
+    assert_eq!(r.email.as_deref(), Some("x@y.z"));
+
+    // once renamed, the old key is no longer read
+    let r: Renamed = serde_json::from_str(r#"{"version":2,"name":"A","mail":"a@b.c"}"#).unwrap();
+    assert_eq!(r.email, None);
+    let err = serde_json::from_str::<Renamed>(r#"{"version":3,"name":"A"}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("missing field `full_name`"));
+
+    let json_str = r#"{"version":3,"full_name":"A","email":null}"#;
+    let r: Renamed = serde_json::from_str(json_str).unwrap();
+    assert_eq!(serde_json::to_string(&r).unwrap(), json_str);
+
+    let r: EnvelopedRenamed =
+        serde_json::from_str(r#"{"version":1,"data":{"name":"A"}}"#).unwrap();
+    assert_eq!(r.full_name, "A");
+    assert!(
+        serde_json::from_str::<EnvelopedRenamed>(r#"{"version":2,"data":{"name":"A"}}"#).is_err()
+    );
+}
+
+fn secs_to_duration(secs: u32) -> Duration {
+    Duration::from_secs(secs.into())
+}
+
+#[version(4)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Timeouts {
+    #[versioned(before = 4, as = "u32", convert = "secs_to_duration")]
+    timeout: Duration,
+    #[versioned(before = 2, as = "u16")]
+    retries: u64,
+}
+
+#[version(3, repr = "keyed")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct KeyedConverted<T> {
+    value: T,
+    #[versioned(before = 3, as = "bool")]
+    count: u8,
+}
+
+#[test]
+fn changed_fields_are_converted_from_older_versions() {
+    let t: Timeouts = serde_json::from_str(r#"{"version":1,"timeout":30,"retries":2}"#).unwrap();
+    assert_eq!(
+        t,
+        Timeouts {
+            timeout: Duration::from_secs(30),
+            retries: 2
+        }
+    );
+    // `retries` is read as a u16 only before version 2
+    assert!(
+        serde_json::from_str::<Timeouts>(r#"{"version":1,"timeout":30,"retries":70000}"#).is_err()
+    );
+    let t: Timeouts = serde_json::from_str(r#"{"version":3,"timeout":5,"retries":70000}"#).unwrap();
+    assert_eq!(t.timeout, Duration::from_secs(5));
+    assert_eq!(t.retries, 70000);
+
+    let json_str = r#"{"version":4,"timeout":{"secs":1,"nanos":500},"retries":1}"#;
+    let t: Timeouts = serde_json::from_str(json_str).unwrap();
+    assert_eq!(t.timeout, Duration::new(1, 500));
+    assert_eq!(serde_json::to_string(&t).unwrap(), json_str);
+    assert!(serde_json::from_str::<Timeouts>(r#"{"version":4,"timeout":5,"retries":1}"#).is_err());
+
+    let k: KeyedConverted<String> =
+        serde_json::from_str(r#"{"v2":{"value":"a","count":true}}"#).unwrap();
+    assert_eq!(
+        k,
+        KeyedConverted {
+            value: "a".to_owned(),
+            count: 1
+        }
+    );
+    let k: KeyedConverted<String> =
+        serde_json::from_str(r#"{"v3":{"value":"a","count":7}}"#).unwrap();
+    assert_eq!(k.count, 7);
+}
+
+#[version(5, removed(legacy_flag: bool, before = 5), removed(mode: String, before = 3))]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Pruned {
+    i: i32,
+}
+
+#[version(2, repr = "envelope", removed(old: u8, before = 2))]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvelopedPruned {
+    i: i32,
+}
+
+#[test]
+fn removed_fields_are_dropped_from_older_versions() {
+    let p: Pruned =
+        serde_json::from_str(r#"{"version":2,"i":1,"legacy_flag":true,"mode":"a"}"#).unwrap();
+    assert_eq!(p, Pruned { i: 1 });
+    let p: Pruned = serde_json::from_str(r#"{"version":4,"i":1,"legacy_flag":false}"#).unwrap();
+    assert_eq!(p, Pruned { i: 1 });
+
+    // the removed field still has to have its old type
+    assert!(serde_json::from_str::<Pruned>(r#"{"version":4,"i":1,"legacy_flag":3}"#).is_err());
+    // and is rejected once removed
+    let err = serde_json::from_str::<Pruned>(r#"{"version":4,"i":1,"mode":"a"}"#)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("field `mode` was removed in version 3"));
+    assert!(serde_json::from_str::<Pruned>(r#"{"version":5,"i":1,"legacy_flag":true}"#).is_err());
+
+    let json_str = r#"{"version":5,"i":1}"#;
+    let p: Pruned = serde_json::from_str(json_str).unwrap();
+    assert_eq!(serde_json::to_string(&p).unwrap(), json_str);
+
+    let p: EnvelopedPruned =
+        serde_json::from_str(r#"{"version":1,"data":{"i":1,"old":7}}"#).unwrap();
+    assert_eq!(p, EnvelopedPruned { i: 1 });
+    assert!(
+        serde_json::from_str::<EnvelopedPruned>(r#"{"version":2,"data":{"i":1,"old":7}}"#).is_err()
+    );
+}
+
+#[version(1, missing = 1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Adopted {
+    i: i32,
+}
+
+#[version("legacy")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct LegacyConfig {
+    name: String,
+}
+
+#[version(2, previous = LegacyConfig, missing = "legacy")]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    port: u16,
+}
+
+impl From<LegacyConfig> for Config {
+    fn from(c: LegacyConfig) -> Self {
+        Config {
+            name: c.name,
+            port: 80,
+        }
+    }
+}
+
+#[version(1, repr = "envelope", missing = 1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct AdoptedEnvelope {
+    i: i32,
+}
+
+#[version(1, repr = "keyed", missing = 1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct AdoptedKeyed {
+    i: i32,
+}
+
+#[test]
+fn payloads_without_a_version_are_legacy() {
+    let a: Adopted = serde_json::from_str(r#"{"i":3}"#).unwrap();
+    assert_eq!(a, Adopted { i: 3 });
+    let a: Adopted = serde_json::from_str(r#"{"version":1,"i":4}"#).unwrap();
+    assert_eq!(a, Adopted { i: 4 });
+    // a version that can't be read is not mistaken for a missing one
+    assert!(serde_json::from_str::<Adopted>(r#"{"version":"x","i":3}"#).is_err());
+    assert!(serde_json::from_str::<Adopted>(r#"{"version":2,"i":3}"#).is_err());
+
+    let c: Config = serde_json::from_str(r#"{"name":"a"}"#).unwrap();
+    assert_eq!(
+        c,
+        Config {
+            name: "a".to_owned(),
+            port: 80
+        }
+    );
+    let c: Config = serde_json::from_str(r#"{"version":2,"name":"a","port":1}"#).unwrap();
+    assert_eq!(c.port, 1);
+
+    let a: AdoptedEnvelope = serde_json::from_str(r#"{"i":3}"#).unwrap();
+    assert_eq!(a, AdoptedEnvelope { i: 3 });
+    let a: AdoptedEnvelope = serde_json::from_str(r#"{"version":1,"data":{"i":4}}"#).unwrap();
+    assert_eq!(a, AdoptedEnvelope { i: 4 });
+
+    let a: AdoptedKeyed = serde_json::from_str(r#"{"i":3}"#).unwrap();
+    assert_eq!(a, AdoptedKeyed { i: 3 });
+    let a: AdoptedKeyed = serde_json::from_str(r#"{"v1":{"i":4}}"#).unwrap();
+    assert_eq!(a, AdoptedKeyed { i: 4 });
+}
+
+#[version(3, forward = 5)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Forward {
+    i: i32,
+}
+
+#[version("1.2.0", forward)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ForwardSemver {
+    i: i32,
+}
+
+#[version(1, repr = "envelope", forward = 3)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ForwardEnvelope {
+    i: i32,
+}
+
+#[version(1, repr = "keyed", forward = 3)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ForwardKeyed {
+    i: i32,
+}
+
+#[version(4)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ForwardNext {
+    i: i32,
+    j: Vec<i32>,
+    k: String,
+}
+
+#[test]
+fn newer_fields_pass_through() {
+    let f: Forward = serde_json::from_str(r#"{"version":4,"i":1,"j":[2],"k":"a"}"#).unwrap();
+    assert_eq!(f.i, 1);
+    assert_eq!(f.extra.names().collect::<Vec<_>>(), ["j", "k"]);
+    assert_eq!(f.extra.version(), Some(&Version::Number(4)));
+    // written back under the version the fields came from, so newer code reads them again
+    let json_str = serde_json::to_string(&f).unwrap();
+    assert_eq!(json_str, r#"{"version":4,"i":1,"j":[2],"k":"a"}"#);
+    assert_eq!(
+        serde_json::from_str::<ForwardNext>(&json_str).unwrap(),
+        ForwardNext {
+            i: 1,
+            j: vec![2],
+            k: "a".to_owned(),
+        }
+    );
+    // without unknown fields there is nothing of the newer version to keep
+    let f: Forward = serde_json::from_str(r#"{"version":4,"i":1}"#).unwrap();
+    assert_eq!(serde_json::to_string(&f).unwrap(), r#"{"version":3,"i":1}"#);
+
+    let f = Forward {
+        i: 2,
+        extra: Default::default(),
+    };
+    let json_str = r#"{"version":3,"i":2}"#;
+    assert_eq!(serde_json::to_string(&f).unwrap(), json_str);
+    assert_eq!(serde_json::from_str::<Forward>(json_str).unwrap(), f);
+    assert!(serde_json::from_str::<Forward>(r#"{"version":2,"i":2}"#).is_err());
+    let err = serde_json::from_str::<Forward>(r#"{"version":6,"i":2}"#)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Forward: expected version 3, found 6"));
+
+    let f: ForwardSemver = serde_json::from_str(r#"{"version":"1.5.0","i":1,"j":2}"#).unwrap();
+    assert!(f.extra.contains("j"));
+    assert_eq!(
+        serde_json::to_string(&f).unwrap(),
+        r#"{"version":"1.5.0","i":1,"j":2}"#
+    );
+    assert!(serde_json::from_str::<ForwardSemver>(r#"{"version":"2.0.0","i":1}"#).is_err());
+
+    let f: ForwardEnvelope = serde_json::from_str(r#"{"version":2,"data":{"i":1,"j":2}}"#).unwrap();
+    assert_eq!(f.extra.len(), 1);
+    assert_eq!(
+        serde_json::to_string(&f).unwrap(),
+        r#"{"version":2,"data":{"i":1,"j":2}}"#
+    );
+
+    let f: ForwardKeyed = serde_json::from_str(r#"{"v3":{"i":1,"j":2}}"#).unwrap();
+    assert_eq!(serde_json::to_string(&f).unwrap(), r#"{"v3":{"i":1,"j":2}}"#);
+}
+
+#[version(1)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Down1 {
+    name: String,
+}
+
+#[version(2, previous = Down1, downgrade)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Down2 {
+    name: String,
+    tags: Vec<String>,
+}
+
+#[version(3, previous = Down2, downgrade)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Down3 {
+    name: String,
+    tags: Vec<String>,
+    port: u16,
+}
+
+impl From<Down1> for Down2 {
+    fn from(d: Down1) -> Self {
+        Down2 {
+            name: d.name,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl From<Down2> for Down3 {
+    fn from(d: Down2) -> Self {
+        Down3 {
+            name: d.name,
+            tags: d.tags,
+            port: 80,
+        }
+    }
+}
+
+impl TryFrom<Down2> for Down1 {
+    type Error = String;
+
+    fn try_from(d: Down2) -> Result<Self, String> {
+        if d.tags.is_empty() {
+            Ok(Down1 { name: d.name })
+        } else {
+            Err(format!("{} tags would be lost", d.tags.len()))
+        }
+    }
+}
+
+impl TryFrom<Down3> for Down2 {
+    type Error = &'static str;
+
+    fn try_from(d: Down3) -> Result<Self, &'static str> {
+        match d.port {
+            80 => Ok(Down2 {
+                name: d.name,
+                tags: d.tags,
+            }),
+            _ => Err("only port 80 is known before version 3"),
+        }
+    }
+}
+
+fn written_as<T: Downgrade>(value: T, version: u64) -> Result<String, serde_json::Error> {
+    let mut json = Vec::new();
+    value.serialize_as_version(version, &mut serde_json::Serializer::new(&mut json))?;
+    Ok(String::from_utf8(json).unwrap())
+}
+
+#[test]
+fn serializes_as_older_versions() {
+    let d = Down3 {
+        name: "a".to_owned(),
+        tags: Vec::new(),
+        port: 80,
+    };
+    assert_eq!(
+        written_as(d.clone(), 3).unwrap(),
+        r#"{"version":3,"name":"a","tags":[],"port":80}"#
+    );
+    assert_eq!(
+        written_as(d.clone(), 2).unwrap(),
+        r#"{"version":2,"name":"a","tags":[]}"#
+    );
+    let json_str = written_as(d.clone(), 1).unwrap();
+    assert_eq!(json_str, r#"{"version":1,"name":"a"}"#);
+    // what older code writes, newer code reads back
+    assert_eq!(serde_json::from_str::<Down3>(&json_str).unwrap(), d);
+
+    // conversions that would lose information fail
+    let tagged = Down3 {
+        tags: vec!["x".to_owned()],
+        ..d.clone()
+    };
+    let err = written_as(tagged, 1).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("Down2 can't be written as version 1: 1 tags would be lost"));
+    let moved = Down3 {
+        port: 81,
+        ..d.clone()
+    };
+    assert!(written_as(moved.clone(), 2).is_err());
+    // versions no type in the chain writes are rejected before converting anything
+    let err = written_as(moved.clone(), 7).err().unwrap();
+    assert!(err.to_string().contains("Down3 has no version 7 to write"));
+    let err = written_as(moved, 0).err().unwrap();
+    assert!(err.to_string().contains("Down3 has no version 0 to write"));
+
+    let d2: Down2 = d.to_version().unwrap();
+    assert_eq!(d2.name, "a");
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(1, field = "schema_version")]
+#[derive(Serialize, Deserialize)]
+struct S {
+    i: i32,
+    schema_version: u8,
+}
+
+fn main() {}
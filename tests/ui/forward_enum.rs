@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(3, forward = 5)]
+#[derive(Serialize, Deserialize)]
+enum E {
+    A,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(3, forward)]
+#[derive(Serialize, Deserialize)]
+struct S {
+    i: i32,
+}
+
+fn main() {}
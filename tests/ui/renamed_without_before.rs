@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(3)]
+#[derive(Serialize, Deserialize)]
+struct S {
+    #[versioned(renamed_from = "old")]
+    i: i32,
+}
+
+fn main() {}
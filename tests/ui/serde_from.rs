@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(1)]
+#[derive(Serialize, Deserialize)]
+#[serde(from = "i32")]
+struct S {
+    i: i32,
+}
+
+impl From<i32> for S {
+    fn from(i: i32) -> S {
+        S { i }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(1)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Other")]
+struct S {
+    i: i32,
+}
+
+#[derive(Serialize)]
+struct Other;
+
+impl From<S> for Other {
+    fn from(_: S) -> Self {
+        Other
+    }
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(1)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", try_from = "i32")]
+struct S {
+    i: i32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use serde_versions::version;
+
+#[version(1)]
+union U {
+    i: i32,
+    f: f32,
+}
+
+fn main() {}
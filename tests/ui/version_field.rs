@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(1)]
+#[derive(Serialize, Deserialize)]
+struct S {
+    version: u8,
+    i: i32,
+}
+
+fn main() {}
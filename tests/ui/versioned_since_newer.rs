@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(2)]
+#[derive(Serialize, Deserialize)]
+struct S {
+    #[versioned(since = 3)]
+    i: i32,
+}
+
+fn main() {}
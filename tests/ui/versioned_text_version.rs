@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version("2024-01")]
+#[derive(Serialize, Deserialize)]
+struct S {
+    #[versioned(until = 2)]
+    i: i32,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(2)]
+#[derive(Serialize, Deserialize)]
+struct S(#[versioned(since = 2)] i32);
+
+fn main() {}
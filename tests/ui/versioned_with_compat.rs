@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_versions::version;
+
+#[version(3, compat = "major")]
+#[derive(Serialize, Deserialize)]
+struct S {
+    #[versioned(since = 2)]
+    i: i32,
+}
+
+fn main() {}